@@ -0,0 +1,62 @@
+use anyhow::Context;
+use engine_types::{PrefabDefinition, Scene, canonical};
+use serde::{Serialize, de::DeserializeOwned};
+use std::path::{Path, PathBuf};
+
+/// Rewrite every scene and prefab in the project in canonical form, returning the files that
+/// weren't already canonical. With `check` set, nothing is written.
+pub fn format_project(project_path: &Path, check: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let mut changed = Vec::new();
+
+    for path in json_files(&project_path.join("scenes"))? {
+        if format_file::<Scene>(&path, check)? {
+            changed.push(path);
+        }
+    }
+
+    for path in json_files(&project_path.join("prefabs"))? {
+        if format_file::<PrefabDefinition>(&path, check)? {
+            changed.push(path);
+        }
+    }
+
+    Ok(changed)
+}
+
+fn format_file<T: Serialize + DeserializeOwned>(path: &Path, check: bool) -> anyhow::Result<bool> {
+    let contents = std::fs::read_to_string(path)?;
+    let parsed: T =
+        serde_json::from_str(&contents).with_context(|| format!("Unable to parse {path:?}"))?;
+    let formatted = canonical::to_string(&parsed)?;
+
+    if formatted == contents {
+        return Ok(false);
+    }
+
+    if !check {
+        std::fs::write(path, formatted)?;
+    }
+
+    Ok(true)
+}
+
+fn json_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            files.push(path);
+        }
+    }
+
+    // Stable output, regardless of what order the filesystem hands us things in
+    files.sort();
+    Ok(files)
+}
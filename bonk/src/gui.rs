@@ -1,5 +1,7 @@
-use crate::{AppState, GuiFn};
-use engine_types::{EditorState, NodeID, PrefabInstance, components::Transform};
+use crate::{AppState, GuiFn, play_mode::PlaySnapshot, snapshot::save_world_to_scene};
+use engine_types::{
+    EditorPlayMode, EditorState, NodeID, PrefabInstance, canonical, components::Transform,
+};
 use hecs::Entity;
 use std::{collections::HashMap, path::Path};
 
@@ -13,40 +15,71 @@ pub fn draw_gui(state: &mut AppState, scene_path: &Path) {
         state.gui_fn = unsafe { get_gui() };
     }
 
-    let scene_dirty = gui_inner(state);
+    let play_mode = state.play_state;
+    let save_world = gui_inner(state);
+    if state.play_state != play_mode {
+        play_mode_changed(state);
+    }
 
     let yak = &mut state.yak;
     yak.finish();
     yak.paint();
 
-    if scene_dirty {
-        std::fs::write(
-            scene_path,
-            serde_json::to_string_pretty(&state.scene).unwrap(),
-        )
-        .unwrap();
+    if save_world {
+        save_world_to_scene(
+            state.engine.world_mut(),
+            &mut state.scene,
+            &mut state.node_entity_map,
+            &state.loaded_prefabs,
+            &state.component_registry,
+        );
+
+        std::fs::write(scene_path, canonical::to_string(&state.scene).unwrap()).unwrap();
+    }
+}
+
+fn play_mode_changed(state: &mut AppState) {
+    match state.play_state {
+        EditorPlayMode::Play => {
+            state.play_snapshot = Some(PlaySnapshot::take(&mut state.engine));
+        }
+        EditorPlayMode::Stop => {
+            let Some(snapshot) = state.play_snapshot.take() else {
+                return;
+            };
+            snapshot.restore(
+                &mut state.engine,
+                &state.keep_changes,
+                &state.node_entity_map,
+            );
+            state.keep_changes.clear();
+        }
     }
 }
 
 fn gui_inner(state: &mut AppState) -> bool {
     let screen_size = state.window.inner_size();
     let screen_size = [screen_size.width as f32, screen_size.height as f32];
+    let mut save_world = false;
     (state.gui_fn)(
         &state.yak.dom(),
         EditorState {
             play_mode: &mut state.play_state,
-            world: &state.engine.world_mut(),
+            keep_changes: &mut state.keep_changes,
+            world: state.engine.world(),
             scene: &mut state.scene,
+            save_world: &mut save_world,
             node_entity_map: &state.node_entity_map,
             loaded_prefabs: &state.loaded_prefabs,
             prefab_definitions: &state.prefab_definitions,
             component_registry: &state.component_registry,
+            system_errors: state.engine.system_errors(),
             engine_texture: state.engine_texture,
             screen_size: screen_size.into(),
             scale: state.window.scale_factor() as _,
         },
     );
-    false
+    save_world
 }
 
 fn nudge(
@@ -54,11 +87,14 @@ fn nudge(
     world: &mut hecs::World,
     node_entity_map: &HashMap<NodeID, Entity>,
 ) {
+    // Saving the world drops despawned nodes, which might include the root
+    let Some(root) = prefab.nodes.get_mut(&0) else {
+        return;
+    };
+    let node_id = root.node_id;
+
     // HAHAAHA! Hahaa! Ha! Yes!
-    let position = prefab
-        .nodes
-        .get_mut(&0)
-        .unwrap()
+    let position = root
         .overrides
         .entry("Transform".to_string())
         .or_insert_with(|| serde_json::to_value(&Transform::default()).unwrap())
@@ -78,9 +114,9 @@ fn nudge(
     // Update it
     position[0] = serde_json::Value::from(next_x);
 
-    let entity = node_entity_map
-        .get(&prefab.nodes.get(&0).unwrap().node_id)
-        .unwrap();
+    let Some(entity) = node_entity_map.get(&node_id) else {
+        return;
+    };
     world
         .entity(*entity)
         .unwrap()
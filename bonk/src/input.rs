@@ -0,0 +1,89 @@
+use engine::{Engine, InputEvent};
+use gilrs::{EventType, Gilrs};
+use winit::{
+    event::{ElementState, MouseScrollDelta, WindowEvent},
+    keyboard::PhysicalKey,
+};
+
+/// Lines are roughly this many pixels, to put both kinds of scrolling in the same units
+const PIXELS_PER_LINE: f32 = 20.;
+
+/// Turn a window event into input for the game, if it's input at all. Keys are named by their
+/// physical position (eg. "KeyW" is W on a QWERTY keyboard), so bindings work on any layout.
+pub fn to_input_event(event: &WindowEvent) -> Option<InputEvent> {
+    match event {
+        WindowEvent::KeyboardInput { event, .. } => {
+            let PhysicalKey::Code(code) = event.physical_key else {
+                return None;
+            };
+            let key = format!("{code:?}");
+            Some(match event.state {
+                ElementState::Pressed => InputEvent::KeyPressed(key),
+                ElementState::Released => InputEvent::KeyReleased(key),
+            })
+        }
+        WindowEvent::CursorMoved { position, .. } => Some(InputEvent::MouseMoved {
+            x: position.x as f32,
+            y: position.y as f32,
+        }),
+        WindowEvent::MouseInput { state, button, .. } => {
+            let button = format!("{button:?}");
+            Some(match state {
+                ElementState::Pressed => InputEvent::MouseButtonPressed(button),
+                ElementState::Released => InputEvent::MouseButtonReleased(button),
+            })
+        }
+        WindowEvent::MouseWheel { delta, .. } => Some(InputEvent::MouseWheel {
+            delta: match delta {
+                MouseScrollDelta::LineDelta(_, y) => *y,
+                MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+            },
+        }),
+        _ => None,
+    }
+}
+
+/// Gamepads don't come through winit, so they're polled separately. Buttons and axes are named
+/// after gilrs' (eg. "South", "LeftStickX").
+pub struct Gamepads {
+    /// Missing if there's no way to talk to gamepads on this machine
+    gilrs: Option<Gilrs>,
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        let gilrs = Gilrs::new()
+            .inspect_err(|e| log::warn!("Unable to use gamepads: {e:?}"))
+            .ok();
+        Self { gilrs }
+    }
+
+    /// Hand everything the gamepads did since the last poll to the game.
+    pub fn poll(&mut self, engine: &mut Engine) {
+        let Some(gilrs) = &mut self.gilrs else {
+            return;
+        };
+
+        while let Some(event) = gilrs.next_event() {
+            let gamepad = usize::from(event.id);
+            let input = match event.event {
+                EventType::ButtonPressed(button, _) => InputEvent::GamepadButtonPressed {
+                    gamepad,
+                    button: format!("{button:?}"),
+                },
+                EventType::ButtonReleased(button, _) => InputEvent::GamepadButtonReleased {
+                    gamepad,
+                    button: format!("{button:?}"),
+                },
+                EventType::AxisChanged(axis, value, _) => InputEvent::GamepadAxis {
+                    gamepad,
+                    axis: format!("{axis:?}"),
+                    value,
+                },
+                EventType::Disconnected => InputEvent::GamepadDisconnected { gamepad },
+                _ => continue,
+            };
+            engine.push_input(input);
+        }
+    }
+}
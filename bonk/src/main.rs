@@ -1,19 +1,26 @@
+mod fmt;
 mod gui;
+mod input;
+mod merge;
+mod play_mode;
+mod snapshot;
 mod yakui_renderer;
 use crate::{
     gui::draw_gui,
+    play_mode::PlaySnapshot,
     yakui_renderer::{YakuiRenderer, ctx},
 };
 use engine::Engine;
 use engine_types::{
     ComponentRegistry, EditorPlayMode, EditorState, GuiFn, InstanceID, InstanceNode, NodeID,
-    Prefab, PrefabDefinition, PrefabInstance, Scene, components::Transform,
+    Prefab, PrefabDefinition, PrefabInstance, Scene, canonical,
+    components::{Parent, Transform},
 };
 use hecs::Entity;
 use lazy_vulkan::{LazyVulkan, StateFamily};
 use std::{
     any::TypeId,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     path::PathBuf,
     sync::{
         Arc, LazyLock, Mutex,
@@ -49,6 +56,7 @@ struct AppState {
     scene: Scene,
     node_entity_map: HashMap<NodeID, Entity>,
     engine: Engine,
+    gamepads: input::Gamepads,
     #[allow(unused)]
     gameplay: GameplayLib,
     #[allow(unused)]
@@ -57,6 +65,10 @@ struct AppState {
     yak: yakui::Yakui,
     engine_texture: yakui::TextureId,
     play_state: EditorPlayMode,
+    /// Taken when play starts, and put back when it stops
+    play_snapshot: Option<PlaySnapshot>,
+    /// Nodes whose changes during play are kept when it stops
+    keep_changes: HashSet<NodeID>,
     yakui_vulkan: Arc<Mutex<yakui_vulkan::YakuiVulkan>>,
     engine_image: Arc<AtomicU64>,
 }
@@ -65,13 +77,19 @@ struct AppState {
 struct App {
     state: Option<AppState>,
     project_path: PathBuf,
+    trace_path: Option<PathBuf>,
+    record_path: Option<PathBuf>,
+    replay_path: Option<PathBuf>,
 }
 
 impl App {
-    fn new(project_path: String) -> Self {
+    fn new(project_path: String, args: &Args) -> Self {
         Self {
             state: None,
             project_path: PathBuf::from(project_path),
+            trace_path: args.trace.clone(),
+            record_path: args.record.clone(),
+            replay_path: args.replay.clone(),
         }
     }
 }
@@ -100,6 +118,9 @@ impl winit::application::ApplicationHandler for App {
             editor_extent,
             format,
         );
+        if self.trace_path.is_some() {
+            engine.profiler().start_trace();
+        }
 
         let mut yak = yakui::Yakui::new();
         yakui_shadcn::add_fonts(&mut yak);
@@ -141,9 +162,14 @@ impl winit::application::ApplicationHandler for App {
         lazy_vulkan.add_sub_renderer(Box::new(yakui_renderer));
 
         let yakui_winit = yakui_winit::YakuiWinit::new(&window);
-        let mut component_registry = get_component_registry();
-        let (mut loaded_prefabs, prefab_definitions) =
-            load_prefabs(self.project_path.join("prefabs"), &mut component_registry);
+        let component_registry = get_component_registry();
+        let prefabs_path = self.project_path.join("prefabs");
+        log::info!("Loading prefabs from path: {:?}", prefabs_path);
+        let prefab_definitions = engine::load_definitions(&prefabs_path).unwrap();
+        // Compiled the same way the engine compiles its own copy, against the same registry
+        let mut loaded_prefabs =
+            engine::compile_prefabs(&prefab_definitions, engine.component_registry());
+        log::info!("Successfully loaded {} prefabs!", loaded_prefabs.len());
 
         let (scene, node_entity_map) = load_scene(
             &self.project_path.join("scenes").join("default.json"),
@@ -157,6 +183,23 @@ impl winit::application::ApplicationHandler for App {
         }
         .unwrap();
 
+        // Gameplay code registers its scene resources on init, so this has to come after
+        engine.load_scene_settings(&scene.settings);
+
+        // Start from the freshly loaded scene, so replays begin where recordings did
+        if let Some(record_path) = &self.record_path {
+            match engine.start_recording(record_path, Some(world_hash())) {
+                Ok(()) => log::info!("Recording session to {record_path:?}"),
+                Err(e) => log::error!("Unable to record session to {record_path:?}: {e:?}"),
+            }
+        }
+        if let Some(replay_path) = &self.replay_path {
+            match engine.start_replay(replay_path, Some(world_hash())) {
+                Ok(()) => log::info!("Replaying session from {replay_path:?}"),
+                Err(e) => log::error!("Unable to replay session from {replay_path:?}: {e:?}"),
+            }
+        }
+
         let gui =
             unsafe { system_loader::GameplayLib::load(LIB_PATH, GUI_LIB_NAME, None) }.unwrap();
 
@@ -176,12 +219,16 @@ impl winit::application::ApplicationHandler for App {
             scene,
             node_entity_map,
             engine,
+            gamepads: input::Gamepads::new(),
             gameplay: gameplay_code,
             gui,
             gui_fn,
             yak,
             engine_texture,
-            play_state: EditorPlayMode::Play,
+            // Start with the scene as authored; nothing runs until play is pressed
+            play_state: EditorPlayMode::Stop,
+            play_snapshot: None,
+            keep_changes: Default::default(),
             yakui_vulkan,
             engine_image,
         })
@@ -227,6 +274,11 @@ impl winit::application::ApplicationHandler for App {
             return;
         };
 
+        // Keys let go of while we're not focused will never reach us, whoever has focus now
+        if let WindowEvent::Focused(false) = event {
+            state.engine.push_input(engine::InputEvent::FocusLost);
+        }
+
         // Next, we hand the event to yakui-winit to see if it wants it
         if state
             .yakui_winit
@@ -235,13 +287,27 @@ impl winit::application::ApplicationHandler for App {
             return;
         }
 
+        // Anything yakui didn't want is the game's
+        if let Some(input) = input::to_input_event(&event) {
+            state.engine.push_input(input);
+        }
+
         // Finally, we see if it's something else we care about
         match event {
-            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::CloseRequested => {
+                if let Some(trace_path) = &self.trace_path {
+                    match state.engine.profiler().write_trace(trace_path) {
+                        Ok(()) => log::info!("Wrote trace to {trace_path:?}"),
+                        Err(e) => log::error!("Unable to write trace to {trace_path:?}: {e:?}"),
+                    }
+                }
+                event_loop.exit()
+            }
             WindowEvent::RedrawRequested => {
                 let swapchain = state.lazy_vulkan.get_drawable();
                 state.lazy_vulkan.begin_commands();
                 let should_run_systems = state.play_state == EditorPlayMode::Play;
+                state.gamepads.poll(&mut state.engine);
                 state.engine.tick_headless(should_run_systems);
 
                 let scene_path = self.project_path.join("scenes").join("default.json");
@@ -258,12 +324,16 @@ impl winit::application::ApplicationHandler for App {
             _ => {}
         }
 
-        unsafe {
+        let reloaded = unsafe {
             state
                 .gameplay
                 .check_and_reload(Some(&mut state.engine))
                 .unwrap()
         };
+        if reloaded {
+            // Scene resources went with the old library, and the new one has registered them again
+            state.engine.reload_scene_resources();
+        }
     }
 
     fn about_to_wait(&mut self, _: &winit::event_loop::ActiveEventLoop) {
@@ -282,27 +352,28 @@ fn load_scene(
 
     if !path.exists() {
         log::info!("Trying to read scene from {path:?}");
-        std::fs::write(
-            path,
-            serde_json::to_string_pretty(&Scene::default()).unwrap(),
-        )
-        .unwrap();
+        std::fs::write(path, canonical::to_string(&Scene::default()).unwrap()).unwrap();
 
         return (Scene::default(), node_entity_map);
     }
 
-    let scene: Scene =
+    let mut scene: Scene =
         serde_json::from_reader(std::io::BufReader::new(std::fs::File::open(path).unwrap()))
             .unwrap();
 
     // Walk through each instance and spawn entities for each node
     for instance in &scene.instances {
-        let prefab_name = &instance.prefab;
-        let prefab = loaded_prefabs.get_mut(prefab_name).unwrap();
-
         for (node_index, instance_node) in &instance.nodes {
-            let node = prefab.nodes.get_mut(*node_index).unwrap();
-            let entity = spawn_entity_for_node(world, node);
+            // Instances without a prefab were baked from the world; their overrides are
+            // everything they have.
+            let entity = match &instance.prefab {
+                Some(prefab_name) => {
+                    let prefab = loaded_prefabs.get_mut(prefab_name).unwrap();
+                    let node = prefab.nodes.get_mut(*node_index).unwrap();
+                    spawn_entity_for_node(world, node)
+                }
+                None => world.spawn(()),
+            };
             node_entity_map.insert(instance_node.node_id, entity);
 
             let mut entity_builder = hecs::EntityBuilderClone::new();
@@ -327,23 +398,90 @@ fn load_scene(
     NEXT_NODE_ID.fetch_add(1, Ordering::Relaxed);
     NEXT_INSTANCE_ID.fetch_add(1, Ordering::Relaxed);
 
+    // Now that everything exists, point entity references at it
+    for instance in &scene.instances {
+        resolve_entity_refs(instance, &node_entity_map, component_registry, world);
+        if let Some(prefab) = instance
+            .prefab
+            .as_ref()
+            .and_then(|name| loaded_prefabs.get(name))
+        {
+            insert_parents(instance, prefab, &node_entity_map, world);
+        }
+    }
+
+    if let Some(camera) = &mut scene.settings.active_camera {
+        let entity = node_entity_map.get(&camera.node_id()).copied();
+        camera.resolve(camera.node_id(), entity);
+    }
+
     (scene, node_entity_map)
 }
 
+/// Resolve every [`engine_types::EntityRef`] in an instance to a live entity.
+///
+/// References in overrides are written as node IDs, but references that came from the prefab
+/// itself are written as node indices within that prefab.
+fn resolve_entity_refs(
+    instance: &PrefabInstance,
+    node_entity_map: &HashMap<NodeID, Entity>,
+    component_registry: &ComponentRegistry,
+    world: &hecs::World,
+) {
+    for instance_node in instance.nodes.values() {
+        let Some(entity) = node_entity_map.get(&instance_node.node_id) else {
+            continue;
+        };
+
+        component_registry.map_entity_refs(world, *entity, &mut |component_name, entity_ref| {
+            let node_id = if instance_node.overrides.contains_key(component_name) {
+                entity_ref.node_id()
+            } else {
+                let node_index = entity_ref.node_id().as_raw();
+                let Some(node) = instance.nodes.get(&node_index) else {
+                    log::warn!(
+                        "{component_name} on node #{} refers to node {node_index}, which isn't in its prefab",
+                        instance_node.node_id
+                    );
+                    return;
+                };
+                node.node_id
+            };
+
+            let entity = node_entity_map.get(&node_id).copied();
+            if entity.is_none() {
+                log::warn!(
+                    "{component_name} on node #{} refers to node #{node_id}, which doesn't exist",
+                    instance_node.node_id
+                );
+            }
+
+            entity_ref.resolve(node_id, entity);
+        });
+    }
+}
+
 fn get_component_registry() -> ComponentRegistry {
     let registry = ComponentRegistry::default();
     // TODO: Register user components
     registry
 }
 
+/// Hashes every component the engine knows about, including gameplay's, to catch replays
+/// diverging.
+fn world_hash() -> engine::WorldHashFn {
+    Box::new(|world, registry| registry.hash_world(world))
+}
+
 fn spawn_prefab(
     name: &str,
     prefab: &mut Prefab,
     scene: &mut Scene,
     world: &mut hecs::World,
     node_entity_map: &mut HashMap<NodeID, Entity>,
+    component_registry: &ComponentRegistry,
 ) {
-    let mut nodes = HashMap::new();
+    let mut nodes = BTreeMap::new();
     for node in &mut prefab.nodes {
         let node_id = next_node_id();
         let entity = spawn_entity_for_node(world, node);
@@ -359,19 +497,42 @@ fn spawn_prefab(
         );
     }
 
-    let instance_id = NEXT_INSTANCE_ID.fetch_add(1, Ordering::Relaxed);
-
-    scene.instances.push(PrefabInstance {
-        instance_id: InstanceID::new(instance_id),
-        prefab: name.to_string(),
+    let instance = PrefabInstance {
+        instance_id: next_instance_id(),
+        prefab: Some(name.to_string()),
         nodes,
-    })
+    };
+    resolve_entity_refs(&instance, node_entity_map, component_registry, world);
+    insert_parents(&instance, prefab, node_entity_map, world);
+    scene.instances.push(instance);
 }
 
-pub fn spawn_entity_for_node(
+/// Give every node's entity a [`Parent`] pointing at its parent node's, so gameplay code can
+/// follow paths like `Player/Weapon/Muzzle`.
+fn insert_parents(
+    instance: &PrefabInstance,
+    prefab: &Prefab,
+    node_entity_map: &HashMap<NodeID, Entity>,
     world: &mut hecs::World,
-    node: &mut engine_types::PrefabNode,
-) -> Entity {
+) {
+    for (node_index, instance_node) in &instance.nodes {
+        let Some(parent_index) = prefab.nodes.get(*node_index).and_then(|node| node.parent) else {
+            continue;
+        };
+        let parent = instance
+            .nodes
+            .get(&parent_index)
+            .and_then(|parent| node_entity_map.get(&parent.node_id));
+        let (Some(entity), Some(parent)) = (node_entity_map.get(&instance_node.node_id), parent)
+        else {
+            continue;
+        };
+
+        world.insert_one(*entity, Parent(*parent)).unwrap();
+    }
+}
+
+pub fn spawn_entity_for_node(world: &mut hecs::World, node: &engine_types::PrefabNode) -> Entity {
     let entity = world.spawn(&node.builder);
     world.insert_one(entity, Transform::default()).unwrap();
     entity
@@ -381,60 +542,51 @@ fn next_node_id() -> NodeID {
     NodeID::new(NEXT_NODE_ID.fetch_add(1, Ordering::Relaxed))
 }
 
+fn next_instance_id() -> InstanceID {
+    InstanceID::new(NEXT_INSTANCE_ID.fetch_add(1, Ordering::Relaxed))
+}
+
 static NEXT_INSTANCE_ID: LazyLock<AtomicUsize> = LazyLock::new(|| AtomicUsize::new(0));
 static NEXT_NODE_ID: LazyLock<AtomicUsize> = LazyLock::new(|| AtomicUsize::new(0));
 
-fn load_prefabs(
-    prefabs_path: PathBuf,
-    component_registry: &mut ComponentRegistry,
-) -> (HashMap<String, Prefab>, HashMap<String, PrefabDefinition>) {
-    log::info!("Loading prefabs from path: {:?}", prefabs_path);
-    let mut prefabs = HashMap::new();
-    let mut prefab_definitions = HashMap::new();
-
-    for entry in std::fs::read_dir(prefabs_path).unwrap() {
-        let entry = entry.unwrap();
-
-        // BLEGH
-        if !entry
-            .path()
-            .extension()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .contains("json")
-        {
-            continue;
-        }
+#[derive(clap::Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Path to the project
+    #[arg(short, long, global = true)]
+    project_path: Option<String>,
 
-        // Blegh!
-        let file_name = entry
-            .path()
-            .file_stem()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
-
-        let reader = std::fs::File::open(entry.path()).unwrap();
-        let definition: PrefabDefinition = serde_json::from_reader(reader).unwrap();
-        let prefab = prefab_compiler::compile(&definition, component_registry);
-
-        prefabs.insert(file_name.clone(), prefab);
-        prefab_definitions.insert(file_name, definition);
-    }
+    /// Record a Chrome trace of the session, written here on exit
+    #[arg(long)]
+    trace: Option<PathBuf>,
+
+    /// Record every tick's input, timing and random seed here, to replay the session later
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
 
-    log::info!("Successfully loaded {} prefabs!", prefabs.len());
+    /// Replay a session recorded with `--record`
+    #[arg(long)]
+    replay: Option<PathBuf>,
 
-    (prefabs, prefab_definitions)
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(clap::Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct Args {
-    /// Path to the project
-    #[arg(short, long)]
-    project_path: String,
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Rewrite the project's scenes and prefabs in canonical form
+    Fmt {
+        /// Don't write anything, just fail if any file isn't canonical
+        #[arg(long)]
+        check: bool,
+    },
+    /// Three-way merge a scene, for use as a git merge driver (`bonk merge %O %A %B`).
+    /// The result is written over OURS.
+    Merge {
+        base: PathBuf,
+        ours: PathBuf,
+        theirs: PathBuf,
+    },
 }
 
 fn main() {
@@ -445,9 +597,34 @@ fn main() {
     println!("EDITOR: Transform type_id: {transform_type_id:?}");
     use clap::Parser;
     let args = Args::parse();
+
+    match args.command {
+        Some(Command::Fmt { check }) => {
+            let project_path = PathBuf::from(args.project_path.expect("No project path!"));
+            let changed = fmt::format_project(&project_path, check).unwrap();
+            for path in &changed {
+                println!(
+                    "{} {path:?}",
+                    if check { "Not canonical:" } else { "Formatted" }
+                );
+            }
+
+            if check && !changed.is_empty() {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Merge { base, ours, theirs }) => {
+            let clean = merge::merge_files(&base, &ours, &theirs).unwrap();
+            std::process::exit(if clean { 0 } else { 1 });
+        }
+        None => {}
+    }
+
     let event_loop = winit::event_loop::EventLoop::new().unwrap();
     event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
-    let mut app = App::new(args.project_path);
+    let project_path = args.project_path.clone().expect("No project path!");
+    let mut app = App::new(project_path, &args);
 
     event_loop.run_app(&mut app).unwrap()
 }
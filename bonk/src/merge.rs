@@ -0,0 +1,34 @@
+use anyhow::Context;
+use engine_types::{Scene, canonical};
+use std::path::Path;
+
+/// Merge `theirs` into `ours` against `base`, writing the result over `ours` like git expects
+/// from a merge driver. Returns `false` if there were conflicts.
+pub fn merge_files(base: &Path, ours: &Path, theirs: &Path) -> anyhow::Result<bool> {
+    let base_scene = read_scene(base)?;
+    let our_scene = read_scene(ours)?;
+    let their_scene = read_scene(theirs)?;
+
+    let result = scene_merge::merge(&base_scene, &our_scene, &their_scene);
+
+    for (old, new) in &result.renumbered {
+        eprintln!("Renumbered their instance #{old} to #{new} as both sides used that ID");
+    }
+
+    for (old, new) in &result.renumbered_nodes {
+        eprintln!("Renumbered their node #{old} to #{new} as both sides used that ID");
+    }
+
+    for conflict in &result.conflicts {
+        eprintln!("CONFLICT: {conflict}");
+    }
+
+    std::fs::write(ours, canonical::to_string(&result.scene)?)?;
+
+    Ok(result.conflicts.is_empty())
+}
+
+fn read_scene(path: &Path) -> anyhow::Result<Scene> {
+    let contents = std::fs::read_to_string(path)?;
+    serde_json::from_str(&contents).with_context(|| format!("Unable to parse scene {path:?}"))
+}
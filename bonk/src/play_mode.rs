@@ -0,0 +1,148 @@
+use engine::{Engine, StateSnapshot};
+use engine_types::{ComponentRegistry, NodeID};
+use hecs::Entity;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// The world and state as they were when play started, so stopping can put the authored scene
+/// back exactly.
+///
+/// Only components registered with the engine (including the gameplay code's own) are compared
+/// and copied. Entities whose registered components didn't change are left alone, but the rest
+/// are rebuilt from the copy, which loses anything unregistered on them. Entities keep their IDs,
+/// so the node entity map stays valid, and references are pointed back at what they were.
+pub struct PlaySnapshot {
+    entities: HashMap<Entity, SavedEntity>,
+    states: StateSnapshot,
+}
+
+/// An entity's registered components, and where the [`engine_types::EntityRef`]s in them
+/// point, by component, in the order they're mapped. References are serialised as node IDs,
+/// which can't tell apart entities spawned from the same prefab.
+#[derive(PartialEq)]
+struct SavedEntity {
+    components: BTreeMap<String, Value>,
+    refs: BTreeMap<String, Vec<Option<Entity>>>,
+}
+
+impl PlaySnapshot {
+    pub fn take(engine: &mut Engine) -> Self {
+        let world = engine.world();
+        let entities = world
+            .iter()
+            .map(|entity_ref| entity_ref.entity())
+            .map(|entity| {
+                let saved = save_entity(world, engine.component_registry(), entity);
+                (entity, saved)
+            })
+            .collect();
+
+        Self {
+            entities,
+            states: engine.state_snapshot(),
+        }
+    }
+
+    /// Put everything back, except the nodes in `keep_changes`, which stay as they are now.
+    pub fn restore(
+        self,
+        engine: &mut Engine,
+        keep_changes: &HashSet<NodeID>,
+        node_entity_map: &HashMap<NodeID, Entity>,
+    ) {
+        let component_registry = engine.component_registry();
+        let world = engine.world();
+        let kept: HashSet<Entity> = keep_changes
+            .iter()
+            .filter_map(|node_id| node_entity_map.get(node_id))
+            .copied()
+            .filter(|entity| world.contains(*entity))
+            .collect();
+
+        // Spawned since play started
+        let spawned: Vec<Entity> = world
+            .iter()
+            .map(|entity_ref| entity_ref.entity())
+            .filter(|entity| !self.entities.contains_key(entity) && !kept.contains(entity))
+            .collect();
+        // Despawned or changed since play started
+        let changed: Vec<(Entity, &SavedEntity)> = self
+            .entities
+            .iter()
+            .filter(|(entity, _)| !kept.contains(*entity))
+            .filter(|(entity, saved)| {
+                !world.contains(**entity)
+                    || save_entity(world, component_registry, **entity) != **saved
+            })
+            .map(|(entity, saved)| (*entity, saved))
+            .collect();
+        let rebuilt: Vec<_> = changed
+            .iter()
+            .map(|(entity, saved)| {
+                let components = component_registry.build_entity(saved.components.clone());
+                (*entity, components)
+            })
+            .collect();
+
+        let world = engine.world_mut();
+        for entity in &spawned {
+            world.despawn(*entity).unwrap();
+        }
+        for (entity, components) in &rebuilt {
+            world.spawn_at(*entity, components);
+        }
+
+        let world = engine.world();
+        for (entity, saved) in &changed {
+            resolve_entity_refs(world, engine.component_registry(), *entity, saved);
+        }
+
+        log::info!(
+            "Restored {} entities and despawned {} from before play, keeping changes to {} entities",
+            changed.len(),
+            spawned.len(),
+            kept.len()
+        );
+        engine.restore_state_snapshot(self.states);
+    }
+}
+
+fn save_entity(
+    world: &hecs::World,
+    component_registry: &ComponentRegistry,
+    entity: Entity,
+) -> SavedEntity {
+    let mut refs: BTreeMap<String, Vec<Option<Entity>>> = BTreeMap::new();
+    component_registry.map_entity_refs(world, entity, &mut |component_name, entity_ref| {
+        refs.entry(component_name.to_string())
+            .or_default()
+            .push(entity_ref.entity());
+    });
+
+    SavedEntity {
+        components: component_registry.serialise_entity(world, entity),
+        refs,
+    }
+}
+
+/// Point the references in a rebuilt entity back at what they pointed to when play started.
+fn resolve_entity_refs(
+    world: &hecs::World,
+    component_registry: &ComponentRegistry,
+    entity: Entity,
+    saved: &SavedEntity,
+) {
+    let mut mapped: HashMap<String, usize> = HashMap::new();
+    component_registry.map_entity_refs(world, entity, &mut |component_name, entity_ref| {
+        let index = mapped.entry(component_name.to_string()).or_default();
+        let target = saved
+            .refs
+            .get(component_name)
+            .and_then(|targets| targets.get(*index))
+            .copied()
+            .flatten()
+            .filter(|target| world.contains(*target));
+        *index += 1;
+        entity_ref.resolve(entity_ref.node_id(), target);
+    });
+}
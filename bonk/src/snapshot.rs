@@ -0,0 +1,117 @@
+use crate::{next_instance_id, next_node_id, spawn_entity_for_node};
+use engine_types::{ComponentRegistry, InstanceNode, NodeID, Prefab, PrefabInstance, Scene};
+use hecs::Entity;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Write the live world back into the scene.
+///
+/// Every node's overrides become whatever differs from a freshly spawned copy of its prefab
+/// node. Entities that were despawned are removed from the scene, and entities that were spawned
+/// at runtime are added as prefab-less instances.
+pub fn save_world_to_scene(
+    world: &hecs::World,
+    scene: &mut Scene,
+    node_entity_map: &mut HashMap<NodeID, Entity>,
+    loaded_prefabs: &HashMap<String, Prefab>,
+    component_registry: &ComponentRegistry,
+) {
+    // Forget entities that are gone, so nothing else goes looking for them
+    node_entity_map.retain(|_, entity| world.contains(*entity));
+
+    for instance in &mut scene.instances {
+        let prefab = instance
+            .prefab
+            .as_ref()
+            .and_then(|name| loaded_prefabs.get(name));
+        let node_ids: HashMap<usize, NodeID> = instance
+            .nodes
+            .iter()
+            .map(|(node_index, node)| (*node_index, node.node_id))
+            .collect();
+
+        instance.nodes.retain(|node_index, node| {
+            let Some(entity) = node_entity_map.get(&node.node_id).copied() else {
+                log::debug!("Node #{} was despawned, removing it", node.node_id);
+                return false;
+            };
+
+            let baseline = prefab_baseline(prefab, *node_index, &node_ids, component_registry);
+            let components = component_registry.serialise_for_scene(world, entity);
+
+            for name in baseline.keys() {
+                if !components.contains_key(name) {
+                    log::warn!(
+                        "Node #{} had {name} removed, but overrides can't express that",
+                        node.node_id
+                    );
+                }
+            }
+
+            node.overrides = components
+                .into_iter()
+                .filter(|(name, value)| baseline.get(name) != Some(value))
+                .collect();
+
+            true
+        });
+    }
+
+    scene
+        .instances
+        .retain(|instance| !instance.nodes.is_empty());
+
+    // Anything left over was spawned at runtime
+    let known: HashSet<Entity> = node_entity_map.values().copied().collect();
+    for entity_ref in world.iter() {
+        let entity = entity_ref.entity();
+        if known.contains(&entity) {
+            continue;
+        }
+
+        let overrides = component_registry.serialise_for_scene(world, entity);
+        if overrides.is_empty() {
+            continue;
+        }
+
+        let node_id = next_node_id();
+        node_entity_map.insert(node_id, entity);
+        scene.instances.push(PrefabInstance {
+            instance_id: next_instance_id(),
+            prefab: None,
+            nodes: [(
+                0,
+                InstanceNode {
+                    node_index: 0,
+                    node_id,
+                    overrides,
+                },
+            )]
+            .into(),
+        });
+    }
+}
+
+/// What a prefab node looks like when it's first spawned into an instance, before overrides.
+fn prefab_baseline(
+    prefab: Option<&Prefab>,
+    node_index: usize,
+    node_ids: &HashMap<usize, NodeID>,
+    component_registry: &ComponentRegistry,
+) -> BTreeMap<String, Value> {
+    let Some(node) = prefab.and_then(|prefab| prefab.nodes.get(node_index)) else {
+        return Default::default();
+    };
+
+    let mut scratch = hecs::World::new();
+    let entity = spawn_entity_for_node(&mut scratch, node);
+
+    // The prefab refers to its own nodes by index, but the live world has node IDs
+    component_registry.map_entity_refs(&scratch, entity, &mut |_, entity_ref| {
+        if let Some(node_id) = node_ids.get(&entity_ref.node_id().as_raw()) {
+            entity_ref.resolve(*node_id, None);
+        }
+    });
+
+    component_registry.serialise_entity(&scratch, entity)
+}
@@ -1,4 +1,5 @@
-use engine_types::{ComponentRegistry, EditorState};
+use engine_types::{ComponentRegistry, EditorPlayMode, EditorState, NodeID, PrefabInstance};
+use std::collections::HashSet;
 use yakui::Direction;
 use yakui::MainAxisSize;
 use yakui::image;
@@ -21,48 +22,78 @@ fn gui_inner(state: EditorState) {
     row.direction = Direction::Right;
     row.main_axis_size = MainAxisSize::Max;
     let mut sidebar_items = Vec::new();
+    if !state.system_errors.is_empty() {
+        let errors = state
+            .system_errors
+            .iter()
+            .rev()
+            .map(|error| SidebarItem::Item {
+                label: format!(
+                    "[{}] {}: {}{}",
+                    error.frame,
+                    error.system,
+                    error.message,
+                    if error.disabled { " (disabled)" } else { "" }
+                ),
+            })
+            .collect();
+
+        sidebar_items.push(SidebarItem::Group {
+            title: format!("Errors ({})", state.system_errors.len()),
+            icon: "".into(),
+            children: errors,
+        });
+    }
+
     for instance in &state.scene.instances {
-        let mut children = Vec::new();
-        let prefab = state.prefab_definitions.get(&instance.prefab).unwrap();
+        let prefab = instance
+            .prefab
+            .as_ref()
+            .map(|name| state.prefab_definitions.get(name).unwrap());
+        let title = format!(
+            "{}#{}",
+            prefab.map_or("Entity", |prefab| prefab.name.as_str()),
+            instance.instance_id
+        );
 
-        let mut components = Vec::new();
-        for (name, value) in &prefab.components {
-            let mut members = Vec::new();
-            for (name, value) in value.as_object().unwrap() {
-                members.push(SidebarItem::Item {
-                    label: format!("{name}: {value}"),
+        // Saving the world drops despawned nodes, which might include the root
+        let Some(instance_node) = instance.nodes.get(&0) else {
+            sidebar_items.push(SidebarItem::Item {
+                label: format!("{title} (root despawned)"),
+            });
+            continue;
+        };
+
+        let mut root_children = Vec::new();
+        if let Some(prefab) = prefab {
+            let mut components = Vec::new();
+            for (name, value) in &prefab.components {
+                let members = component_members(value);
+
+                components.push(SidebarItem::Group {
+                    title: name.clone(),
+                    icon: "".into(),
+                    children: members,
                 })
             }
 
-            components.push(SidebarItem::Group {
-                title: name.clone(),
+            let mut children = Vec::new();
+            children.push(SidebarItem::Group {
+                title: "Components".into(),
                 icon: "".into(),
-                children: members,
-            })
+                children: components,
+            });
+
+            root_children.push(SidebarItem::Group {
+                title: format!("Prefab"),
+                icon: icons::hammer(),
+                children,
+            });
         }
 
-        children.push(SidebarItem::Group {
-            title: "Components".into(),
-            icon: "".into(),
-            children: components,
-        });
-
-        let mut root_children = Vec::new();
-        root_children.push(SidebarItem::Group {
-            title: format!("Prefab"),
-            icon: icons::hammer(),
-            children,
-        });
-
-        let instance_node = instance.nodes.get(&0).unwrap();
         let mut components = Vec::new();
         for (name, value) in &instance_node.overrides {
-            let mut members = Vec::new();
-            for (name, value) in value.as_object().unwrap() {
-                members.push(SidebarItem::Item {
-                    label: format!("{name}: {value}"),
-                })
-            }
+            let members = component_members(value);
 
             components.push(SidebarItem::Group {
                 title: name.clone(),
@@ -84,55 +115,64 @@ fn gui_inner(state: EditorState) {
             children,
         });
 
-        let mut children = Vec::new();
-        let mut components = Vec::new();
-        let entity = state.node_entity_map.get(&instance_node.node_id).unwrap();
-
-        for name in prefab
-            .components
-            .keys()
-            .chain(instance_node.overrides.keys())
-        {
-            let component =
-                state
-                    .component_registry
-                    .get_component_as_value(name, state.world, *entity);
-
-            let mut members = Vec::new();
-            for (name, value) in component.as_object().unwrap() {
-                members.push(SidebarItem::Item {
-                    label: format!("{name}: {value}"),
+        // Not spawned yet, or despawned since the scene was saved
+        if let Some(entity) = state.node_entity_map.get(&instance_node.node_id) {
+            let mut children = Vec::new();
+            let mut components = Vec::new();
+
+            for name in prefab
+                .into_iter()
+                .flat_map(|prefab| prefab.components.keys())
+                .chain(instance_node.overrides.keys())
+            {
+                let component =
+                    state
+                        .component_registry
+                        .get_component_as_value(name, state.world, *entity);
+
+                let members = component_members(component);
+
+                components.push(SidebarItem::Group {
+                    title: name.clone(),
+                    icon: "".into(),
+                    children: members,
                 })
             }
 
-            components.push(SidebarItem::Group {
-                title: name.clone(),
+            children.push(SidebarItem::Group {
+                title: "Components".into(),
                 icon: "".into(),
-                children: members,
-            })
+                children: components,
+            });
+
+            root_children.push(SidebarItem::Group {
+                title: format!("Entity (#{})", entity.id()),
+                icon: icons::play(),
+                children,
+            });
         }
 
-        children.push(SidebarItem::Group {
-            title: "Components".into(),
-            icon: "".into(),
-            children: components,
-        });
-
-        root_children.push(SidebarItem::Group {
-            title: format!("Entity (#{})", entity.id()),
-            icon: icons::play(),
-            children,
-        });
-
         sidebar_items.push(SidebarItem::Group {
-            title: format!("{}#{}", prefab.name, instance.instance_id),
+            title,
             icon: icons::hexagon(),
             children: root_children,
         });
     }
 
+    let playing = *state.play_mode == EditorPlayMode::Play;
     row.show(|| {
-        sidebar(format!("{} Bonk", icons::hammer()), &sidebar_items);
+        yakui::column(|| {
+            if yakui::button(if playing { "Stop" } else { "Play" }).clicked {
+                state.play_mode.flip();
+            }
+            if playing {
+                keep_changes(&state.scene.instances, state.keep_changes);
+            }
+            if yakui::button("Save world to scene").clicked {
+                *state.save_world = true;
+            }
+            sidebar(format!("{} Bonk", icons::hammer()), &sidebar_items);
+        });
         image(
             state.engine_texture,
             [
@@ -143,6 +183,54 @@ fn gui_inner(state: EditorState) {
     });
 }
 
+/// A component's fields, or just its value if it isn't a struct (eg. `Name`).
+fn component_members(value: &serde_json::Value) -> Vec<SidebarItem> {
+    let Some(fields) = value.as_object() else {
+        return vec![SidebarItem::Item {
+            label: value.to_string(),
+        }];
+    };
+
+    fields
+        .iter()
+        .map(|(name, value)| SidebarItem::Item {
+            label: format!("{name}: {value}"),
+        })
+        .collect()
+}
+
+/// While playing, pick which instances keep their changes when play stops. Every node in the
+/// instance is kept, not just the root.
+fn keep_changes(instances: &[PrefabInstance], keep_changes: &mut HashSet<NodeID>) {
+    for instance in instances {
+        let node_ids: Vec<NodeID> = instance.nodes.values().map(|node| node.node_id).collect();
+        // Saving the world can leave an instance with no nodes at all
+        if node_ids.is_empty() {
+            continue;
+        }
+
+        yakui::row(|| {
+            let kept = node_ids
+                .iter()
+                .all(|node_id| keep_changes.contains(node_id));
+            if yakui::checkbox(kept).checked != kept {
+                for node_id in &node_ids {
+                    if kept {
+                        keep_changes.remove(node_id);
+                    } else {
+                        keep_changes.insert(*node_id);
+                    }
+                }
+            }
+            yakui::label(format!(
+                "Keep {}#{}",
+                instance.prefab.as_deref().unwrap_or("Entity"),
+                instance.instance_id
+            ));
+        });
+    }
+}
+
 #[unsafe(no_mangle)]
 pub fn get_bonk_gui() -> GuiFn {
     Box::new(gui)
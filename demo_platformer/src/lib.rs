@@ -1,12 +1,97 @@
 use std::{any::TypeId, ffi::CString, str::FromStr};
 
-use engine::{Engine, TickData, components::Transform};
-use glam::Quat;
+use engine::{Engine, Input, SpawnedPrefab, TickData, Timers, components::Transform};
+use glam::{Quat, Vec3};
+
+/// Radians per second
+const SPIN_SPEED: f32 = 2.4;
+
+/// Metres per second
+const MOVE_SPEED: f32 = 3.0;
+
+/// Metres, all at once
+const JUMP_HEIGHT: f32 = 1.0;
+
+/// Seconds between jumps
+const JUMP_COOLDOWN: f32 = 0.75;
+
+const QUICK_SAVE_SLOT: usize = 0;
+
+/// Metres above the origin
+const CUBE_DROP_HEIGHT: f32 = 3.0;
+
+/// Cubes dropped by `cube_system`, so they can be cleared again
+#[derive(Default)]
+struct DroppedCubes(Vec<SpawnedPrefab>);
 
 /// Example gameplay system
 fn my_system(tick: &mut TickData) -> anyhow::Result<()> {
     for (_, transform) in tick.world.query::<&mut Transform>().iter() {
-        transform.rotation *= Quat::from_rotation_y(-0.04);
+        transform.rotation *= Quat::from_rotation_y(-SPIN_SPEED * tick.dt);
+    }
+
+    Ok(())
+}
+
+/// Move everything with the player's input. See `input.json` for the bindings.
+fn move_system(tick: &mut TickData) -> anyhow::Result<()> {
+    let dt = tick.dt;
+    let (input, timers) = tick.get_states::<(Input, Timers)>()?;
+    let mut movement = Vec3::X * input.axis("move_x") * MOVE_SPEED * dt;
+    if input.action_just_pressed("jump") && !timers.is_running("jump_cooldown") {
+        movement.y += JUMP_HEIGHT;
+        timers.start("jump_cooldown", JUMP_COOLDOWN);
+    }
+
+    for (_, transform) in tick.world.query::<&mut Transform>().iter() {
+        transform.position += movement;
+    }
+
+    Ok(())
+}
+
+/// Quick save to, and load from, the first slot. A real checkpoint would do the same when the
+/// player reaches it.
+fn save_system(tick: &mut TickData) -> anyhow::Result<()> {
+    let input = tick.get_state::<Input>()?;
+    let (save, load) = (
+        input.action_just_pressed("quick_save"),
+        input.action_just_pressed("quick_load"),
+    );
+
+    if save {
+        tick.save_game(QUICK_SAVE_SLOT, "default");
+    } else if load {
+        tick.load_game(QUICK_SAVE_SLOT);
+    }
+
+    Ok(())
+}
+
+/// Drop a cube from `prefabs/cube.json`, or clear them all away.
+fn cube_system(tick: &mut TickData) -> anyhow::Result<()> {
+    let input = tick.get_state::<Input>()?;
+    let (drop, clear) = (
+        input.action_just_pressed("drop_cube"),
+        input.action_just_pressed("clear_cubes"),
+    );
+
+    if drop {
+        let transform = Transform {
+            position: Vec3::Y * CUBE_DROP_HEIGHT,
+            ..Default::default()
+        };
+        let cube = tick.spawn_prefab("cube", transform)?;
+        tick.get_or_insert_state_with(DroppedCubes::default)
+            .0
+            .push(cube);
+    }
+
+    if clear {
+        let cubes = std::mem::take(&mut tick.get_or_insert_state_with(DroppedCubes::default).0);
+        for cube in cubes {
+            tick.despawn_prefab(cube.root);
+        }
     }
 
     Ok(())
@@ -19,8 +104,7 @@ pub extern "C" fn init(engine_ptr: *mut Engine) {
     let transform_type_id = TypeId::of::<engine_types::components::Transform>();
     println!("INIT: Transform type_id: {transform_type_id:?}");
     let engine = get_engine(engine_ptr);
-    engine.register_system("my_system", my_system);
-    engine.insert_state(0 as usize);
+    register(engine);
 }
 
 /// Called by the loader on reload
@@ -28,8 +112,16 @@ pub extern "C" fn init(engine_ptr: *mut Engine) {
 pub extern "C" fn reload(engine_ptr: *mut Engine) {
     println!("RELOAD!");
     let engine = get_engine(engine_ptr);
-    // overwrites previous one
+    // The engine dropped everything from the old library, so register it all again
+    register(engine);
+}
+
+fn register(engine: &mut Engine) {
     engine.register_system("my_system", my_system);
+    engine.register_system("move_system", move_system);
+    engine.register_system("save_system", save_system);
+    engine.register_system("cube_system", cube_system);
+    engine.insert_persistent_state("counter", 0 as usize);
 }
 
 fn get_engine<'a>(engine_ptr: *mut Engine) -> &'a mut Engine {
@@ -38,3 +130,59 @@ fn get_engine<'a>(engine_ptr: *mut Engine) -> &'a mut Engine {
     let engine = unsafe { Engine::from_ptr(engine_ptr, version.as_ptr()) }.unwrap();
     engine
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine::InputEvent;
+
+    #[test]
+    fn test_my_system() {
+        let mut engine = Engine::new_simulation(".");
+        register(&mut engine);
+        let entity = engine.world_mut().spawn((Transform::default(),));
+
+        engine.step(0.5);
+
+        let transform = engine.world().get::<&Transform>(entity).unwrap();
+        let expected = Quat::from_rotation_y(-SPIN_SPEED * 0.5);
+        assert!(transform.rotation.abs_diff_eq(expected, 1e-5));
+    }
+
+    #[test]
+    fn test_move_system() {
+        let mut engine = Engine::new_simulation(".");
+        register(&mut engine);
+        let entity = engine.world_mut().spawn((Transform::default(),));
+
+        engine.push_input(InputEvent::KeyPressed("KeyD".into()));
+        engine.push_input(InputEvent::KeyPressed("Space".into()));
+        engine.step(0.5);
+        // Still held, but it's not a new jump
+        engine.step(0.5);
+        // A new jump, but too soon after the last
+        engine.push_input(InputEvent::KeyReleased("Space".into()));
+        engine.step(0.);
+        engine.push_input(InputEvent::KeyPressed("Space".into()));
+        engine.step(0.);
+
+        let transform = engine.world().get::<&Transform>(entity).unwrap();
+        let expected = Vec3::new(MOVE_SPEED, JUMP_HEIGHT, 0.);
+        assert!(transform.position.abs_diff_eq(expected, 1e-5));
+    }
+
+    #[test]
+    fn test_cube_system() {
+        let mut engine = Engine::new_simulation(".");
+        register(&mut engine);
+
+        engine.push_input(InputEvent::KeyPressed("KeyC".into()));
+        engine.step(0.);
+        assert_eq!(engine.world().len(), 1);
+        assert!(engine::hierarchy::find_by_name(engine.world(), "root").is_some());
+
+        engine.push_input(InputEvent::KeyPressed("KeyX".into()));
+        engine.step(0.);
+        assert_eq!(engine.world().len(), 0);
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+
+use hecs::Entity;
+
+use crate::StateManager;
+
+/// Called with an entity that `C` was just added to.
+pub type AddHook = fn(&mut hecs::World, &mut StateManager, Entity);
+
+/// Called with an entity that `C` was just removed from (or that was despawned), and the last
+/// value it had.
+pub type RemoveHook<C> = fn(&mut hecs::World, &mut StateManager, Entity, C);
+
+/// What happened to tracked components during the last tick. Systems get it with
+/// `tick.get_state::<Changes>()`; components are tracked with [`crate::Engine::track_changes`].
+///
+/// Changes aren't recorded as they happen: each tick, every tracked component is compared with
+/// a copy from the tick before. That's a pass over every entity with one, per tracked type, plus
+/// a clone of anything added or changed, so only track components that something actually needs
+/// to watch.
+#[derive(Debug, Default)]
+pub struct Changes {
+    added: HashMap<TypeId, Vec<Entity>>,
+    changed: HashMap<TypeId, Vec<Entity>>,
+    removed: HashMap<TypeId, Vec<Entity>>,
+}
+
+impl Changes {
+    /// Entities that got a `C`, including newly spawned ones.
+    pub fn added<C: 'static>(&self) -> &[Entity] {
+        get::<C>(&self.added)
+    }
+
+    /// Entities whose `C` is different to how it was. Doesn't include ones it was just added to.
+    pub fn changed<C: 'static>(&self) -> &[Entity] {
+        get::<C>(&self.changed)
+    }
+
+    /// Entities that lost their `C`, including despawned ones.
+    pub fn removed<C: 'static>(&self) -> &[Entity] {
+        get::<C>(&self.removed)
+    }
+
+    /// Entities that need looking at again: the ones `C` was added to or changed on.
+    pub fn added_or_changed<C: 'static>(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.added::<C>().iter().chain(self.changed::<C>()).copied()
+    }
+
+    pub fn is_changed<C: 'static>(&self, entity: Entity) -> bool {
+        self.changed::<C>().contains(&entity)
+    }
+}
+
+fn get<C: 'static>(entities: &HashMap<TypeId, Vec<Entity>>) -> &[Entity] {
+    entities
+        .get(&TypeId::of::<C>())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+trait AnyTracker {
+    /// Compare the world with the last copy, noting what's different in `changes`.
+    fn update(&mut self, world: &hecs::World, changes: &mut Changes);
+    fn run_hooks(&mut self, world: &mut hecs::World, state: &mut StateManager, changes: &Changes);
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+struct Tracker<C> {
+    /// Every tracked component as of last tick, and the last tick it was seen
+    last: HashMap<Entity, (C, u64)>,
+    tick: u64,
+    /// Removed this tick, kept for the hooks
+    removed: Vec<(Entity, C)>,
+    on_add: Vec<AddHook>,
+    on_remove: Vec<RemoveHook<C>>,
+}
+
+impl<C> Default for Tracker<C> {
+    fn default() -> Self {
+        Self {
+            last: Default::default(),
+            tick: 0,
+            removed: Default::default(),
+            on_add: Default::default(),
+            on_remove: Default::default(),
+        }
+    }
+}
+
+impl<C: hecs::Component + Clone + PartialEq> AnyTracker for Tracker<C> {
+    fn update(&mut self, world: &hecs::World, changes: &mut Changes) {
+        self.tick += 1;
+        let tick = self.tick;
+        self.removed.clear();
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        let mut seen = 0;
+
+        // Only what's new or different is copied
+        for (entity, component) in world.query::<&C>().iter() {
+            seen += 1;
+            match self.last.get_mut(&entity) {
+                None => {
+                    added.push(entity);
+                    self.last.insert(entity, (component.clone(), tick));
+                }
+                Some((last, last_seen)) => {
+                    *last_seen = tick;
+                    if last != component {
+                        changed.push(entity);
+                        last.clone_from(component);
+                    }
+                }
+            }
+        }
+
+        // Anything that wasn't seen this time has gone
+        if seen < self.last.len() {
+            let removed = &mut self.removed;
+            self.last.retain(|entity, (component, last_seen)| {
+                if *last_seen == tick {
+                    return true;
+                }
+                removed.push((*entity, component.clone()));
+                false
+            });
+        }
+
+        let type_id = TypeId::of::<C>();
+        let removed = self.removed.iter().map(|(entity, _)| *entity).collect();
+        changes.added.insert(type_id, added);
+        changes.changed.insert(type_id, changed);
+        changes.removed.insert(type_id, removed);
+    }
+
+    fn run_hooks(&mut self, world: &mut hecs::World, state: &mut StateManager, changes: &Changes) {
+        if !self.on_add.is_empty() {
+            for entity in changes.added::<C>() {
+                for hook in &self.on_add {
+                    hook(world, state, *entity);
+                }
+            }
+        }
+
+        for (entity, component) in self.removed.drain(..) {
+            for hook in &self.on_remove {
+                hook(world, state, entity, component.clone());
+            }
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Every tracked component type, and its hooks.
+#[derive(Default)]
+pub(crate) struct ChangeTrackers {
+    trackers: HashMap<TypeId, Box<dyn AnyTracker>>,
+}
+
+impl ChangeTrackers {
+    pub fn track<C: hecs::Component + Clone + PartialEq>(&mut self) {
+        self.tracker::<C>();
+    }
+
+    pub fn on_add<C: hecs::Component + Clone + PartialEq>(&mut self, hook: AddHook) {
+        self.tracker::<C>().on_add.push(hook);
+    }
+
+    pub fn on_remove<C: hecs::Component + Clone + PartialEq>(&mut self, hook: RemoveHook<C>) {
+        self.tracker::<C>().on_remove.push(hook);
+    }
+
+    fn tracker<C: hecs::Component + Clone + PartialEq>(&mut self) -> &mut Tracker<C> {
+        self.trackers
+            .entry(TypeId::of::<C>())
+            .or_insert_with(|| Box::new(Tracker::<C>::default()))
+            .as_any_mut()
+            .downcast_mut()
+            .unwrap()
+    }
+
+    /// Find what changed since last time, then run hooks for it.
+    pub fn update(&mut self, world: &mut hecs::World, state: &mut StateManager) -> Changes {
+        let mut changes = Changes::default();
+        for tracker in self.trackers.values_mut() {
+            tracker.update(world, &mut changes);
+        }
+        for tracker in self.trackers.values_mut() {
+            tracker.run_hooks(world, state, &changes);
+        }
+        changes
+    }
+
+    pub fn clear(&mut self) {
+        self.trackers.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::Transform;
+
+    type Health = f32;
+
+    fn count_added(_: &mut hecs::World, state: &mut StateManager, _: Entity) {
+        *state.get_or_insert_with(|| 0_usize) += 1;
+    }
+
+    fn remember_removed(_: &mut hecs::World, state: &mut StateManager, _: Entity, health: Health) {
+        state.insert_state(health);
+    }
+
+    #[test]
+    fn test_changes() {
+        let mut trackers = ChangeTrackers::default();
+        trackers.track::<Transform>();
+        trackers.on_add::<Health>(count_added);
+        trackers.on_remove::<Health>(remember_removed);
+
+        let mut world = hecs::World::new();
+        let mut state = StateManager::default();
+        let a = world.spawn((Transform::default(), 10. as Health));
+        let b = world.spawn((Transform::default(),));
+
+        let changes = trackers.update(&mut world, &mut state);
+        assert_eq!(changes.added::<Transform>().len(), 2);
+        assert_eq!(changes.added::<Health>(), [a]);
+        assert_eq!(*state.get_state::<usize>().unwrap(), 1);
+
+        // Nothing happened
+        let changes = trackers.update(&mut world, &mut state);
+        assert!(changes.added::<Transform>().is_empty());
+        assert!(changes.changed::<Transform>().is_empty());
+
+        world.get::<&mut Transform>(b).unwrap().position.x = 1.;
+        world.remove_one::<Health>(a).unwrap();
+        let changes = trackers.update(&mut world, &mut state);
+        assert_eq!(changes.changed::<Transform>(), [b]);
+        assert!(changes.is_changed::<Transform>(b));
+        assert_eq!(changes.removed::<Health>(), [a]);
+        assert_eq!(*state.get_state::<Health>().unwrap(), 10.);
+
+        world.despawn(b).unwrap();
+        let changes = trackers.update(&mut world, &mut state);
+        assert_eq!(changes.removed::<Transform>(), [b]);
+        assert_eq!(changes.added_or_changed::<Transform>().count(), 0);
+    }
+}
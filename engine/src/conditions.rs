@@ -0,0 +1,24 @@
+//! Ready-made run conditions, for [`crate::SystemDescriptor::run_if`].
+
+use crate::{StateManager, Time};
+
+/// Run once every `n` ticks.
+pub fn every_n_ticks(n: u64) -> impl Fn(&StateManager) -> bool + Send + Sync + 'static {
+    move |state| {
+        state
+            .get::<Time>()
+            .is_some_and(|time| time.frame_count % n.max(1) == 0)
+    }
+}
+
+/// Run only while state `S` has been inserted.
+pub fn state_exists<S: 'static>() -> impl Fn(&StateManager) -> bool + Send + Sync + 'static {
+    |state| state.contains_state::<S>()
+}
+
+/// Run only while state `S` is equal to `value`, eg. `in_state(GameState::Playing)`.
+pub fn in_state<S: PartialEq + Send + Sync + 'static>(
+    value: S,
+) -> impl Fn(&StateManager) -> bool + Send + Sync + 'static {
+    move |state| state.get::<S>() == Some(&value)
+}
@@ -0,0 +1,141 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+/// Events sent by systems. An event can be read during the frame it was sent and the frame
+/// after, then it's gone. Each system reads every event once, wherever it is in the schedule.
+#[derive(Default)]
+pub(crate) struct Events {
+    // Parallel systems send and read at the same time, hence the lock
+    channels: Mutex<HashMap<TypeId, Box<dyn AnyChannel>>>,
+}
+
+impl Events {
+    pub fn send<E: Clone + Send + 'static>(&self, event: E) {
+        self.lock()
+            .entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(Channel::<E>::default()))
+            .as_any_mut()
+            .downcast_mut::<Channel<E>>()
+            .unwrap()
+            .send(event);
+    }
+
+    /// Every `E` that `reader` hasn't seen yet, oldest first.
+    pub fn read<E: Clone + Send + 'static>(&self, reader: &str) -> Vec<E> {
+        self.lock()
+            .get_mut(&TypeId::of::<E>())
+            .and_then(|channel| channel.as_any_mut().downcast_mut::<Channel<E>>())
+            .map(|channel| channel.read(reader))
+            .unwrap_or_default()
+    }
+
+    /// Called at the end of every frame. Drops events that are two frames old.
+    pub fn update(&mut self) {
+        let channels = self
+            .channels
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        for channel in channels.values_mut() {
+            channel.update();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<TypeId, Box<dyn AnyChannel>>> {
+        // A system panicking mid-send shouldn't take events down for everyone else
+        self.channels.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+trait AnyChannel: Send {
+    fn update(&mut self);
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+struct Channel<E> {
+    /// Last frame's events, then this frame's
+    previous: Vec<E>,
+    current: Vec<E>,
+    /// The index of the first event in `previous`, counting every event ever sent
+    start: u64,
+    /// How far each reader has got, by the same count
+    cursors: HashMap<String, u64>,
+}
+
+impl<E> Default for Channel<E> {
+    fn default() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            start: 0,
+            cursors: HashMap::new(),
+        }
+    }
+}
+
+impl<E: Clone> Channel<E> {
+    fn send(&mut self, event: E) {
+        self.current.push(event);
+    }
+
+    fn read(&mut self, reader: &str) -> Vec<E> {
+        let end = self.start + (self.previous.len() + self.current.len()) as u64;
+        let cursor = self.cursors.entry(reader.to_string()).or_insert(self.start);
+        let skip = cursor.saturating_sub(self.start) as usize;
+        *cursor = end;
+
+        self.previous
+            .iter()
+            .chain(&self.current)
+            .skip(skip)
+            .cloned()
+            .collect()
+    }
+}
+
+impl<E: Send + 'static> AnyChannel for Channel<E> {
+    fn update(&mut self) {
+        self.start += self.previous.len() as u64;
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct CoinCollected(u32);
+
+    #[test]
+    fn test_events() {
+        let mut events = Events::default();
+        assert!(events.read::<CoinCollected>("score").is_empty());
+
+        events.send(CoinCollected(1));
+        assert_eq!(events.read::<CoinCollected>("score"), [CoinCollected(1)]);
+        assert!(events.read::<CoinCollected>("score").is_empty());
+
+        // Readers that run before the sender see it next frame
+        events.update();
+        events.send(CoinCollected(2));
+        assert_eq!(
+            events.read::<CoinCollected>("sound"),
+            [CoinCollected(1), CoinCollected(2)]
+        );
+        assert_eq!(events.read::<CoinCollected>("score"), [CoinCollected(2)]);
+
+        // Events only last two frames
+        events.update();
+        events.update();
+        assert!(events.read::<CoinCollected>("ui").is_empty());
+        events.send(CoinCollected(3));
+        assert_eq!(events.read::<CoinCollected>("score"), [CoinCollected(3)]);
+    }
+}
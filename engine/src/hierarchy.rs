@@ -0,0 +1,94 @@
+//! Finding entities spawned from prefabs by their [`Name`], and walking the [`Parent`]s between
+//! them.
+
+use hecs::Entity;
+
+use crate::components::{Name, Parent};
+
+/// The first entity called `name`, anywhere in the world.
+pub fn find_by_name(world: &hecs::World, name: &str) -> Option<Entity> {
+    world
+        .query::<&Name>()
+        .iter()
+        .find(|(_, entity_name)| entity_name.0 == name)
+        .map(|(entity, _)| entity)
+}
+
+/// Every entity directly under `parent`.
+pub fn children(world: &hecs::World, parent: Entity) -> Vec<Entity> {
+    world
+        .query::<&Parent>()
+        .iter()
+        .filter(|(_, p)| p.0 == parent)
+        .map(|(entity, _)| entity)
+        .collect()
+}
+
+/// Follow a path of names down from `root`, eg. `Weapon/Muzzle`.
+pub fn find_child(world: &hecs::World, root: Entity, path: &str) -> Option<Entity> {
+    path.split('/')
+        .filter(|name| !name.is_empty())
+        .try_fold(root, |parent, name| {
+            children(world, parent)
+                .into_iter()
+                .find(|child| has_name(world, *child, name))
+        })
+}
+
+/// Find an entity by its full path from the root of its prefab instance, eg.
+/// `Player/Weapon/Muzzle`. If several instances match, any one of them might be returned.
+pub fn find_by_path(world: &hecs::World, path: &str) -> Option<Entity> {
+    let (root_name, rest) = path.split_once('/').unwrap_or((path, ""));
+    world
+        .query::<&Name>()
+        .without::<&Parent>()
+        .iter()
+        .filter(|(_, name)| name.0 == root_name)
+        .find_map(|(root, _)| find_child(world, root, rest))
+}
+
+/// The path to `entity` from the root of its instance, the opposite of [`find_by_path`].
+pub fn path_of(world: &hecs::World, entity: Entity) -> Option<String> {
+    let mut names = Vec::new();
+    let mut current = Some(entity);
+    while let Some(entity) = current {
+        names.push(world.get::<&Name>(entity).ok()?.0.clone());
+        current = world.get::<&Parent>(entity).ok().map(|parent| parent.0);
+    }
+    names.reverse();
+    Some(names.join("/"))
+}
+
+fn has_name(world: &hecs::World, entity: Entity, name: &str) -> bool {
+    world
+        .get::<&Name>(entity)
+        .is_ok_and(|entity_name| entity_name.0 == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> Name {
+        Name(name.into())
+    }
+
+    #[test]
+    fn test_paths() {
+        let mut world = hecs::World::new();
+        let player = world.spawn((name("Player"),));
+        let weapon = world.spawn((name("Weapon"), Parent(player)));
+        let muzzle = world.spawn((name("Muzzle"), Parent(weapon)));
+        let enemy = world.spawn((name("Enemy"),));
+        world.spawn((name("Weapon"), Parent(enemy)));
+
+        assert_eq!(find_by_name(&world, "Muzzle"), Some(muzzle));
+        assert_eq!(children(&world, player), [weapon]);
+        assert_eq!(find_child(&world, player, "Weapon/Muzzle"), Some(muzzle));
+        assert_eq!(find_by_path(&world, "Player/Weapon/Muzzle"), Some(muzzle));
+        assert_eq!(find_by_path(&world, "Player"), Some(player));
+        assert_eq!(find_by_path(&world, "Enemy/Weapon/Muzzle"), None);
+        assert_eq!(find_by_path(&world, "Weapon"), None);
+        assert_eq!(path_of(&world, muzzle).unwrap(), "Player/Weapon/Muzzle");
+    }
+}
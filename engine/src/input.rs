@@ -0,0 +1,357 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Something the player did. Fed to the engine with [`crate::Engine::push_input`], and sent to
+/// systems as an event at the start of the next tick. Keys and buttons are named, so recordings
+/// don't depend on the windowing library.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum InputEvent {
+    KeyPressed(String),
+    KeyReleased(String),
+    MouseMoved {
+        x: f32,
+        y: f32,
+    },
+    MouseButtonPressed(String),
+    MouseButtonReleased(String),
+    MouseWheel {
+        delta: f32,
+    },
+    GamepadButtonPressed {
+        gamepad: usize,
+        button: String,
+    },
+    GamepadButtonReleased {
+        gamepad: usize,
+        button: String,
+    },
+    GamepadAxis {
+        gamepad: usize,
+        axis: String,
+        value: f32,
+    },
+    /// Let go of everything the gamepad was holding or pushing
+    GamepadDisconnected {
+        gamepad: usize,
+    },
+    /// The window lost focus, so keys and mouse buttons let go of elsewhere will never be seen.
+    /// Let go of them all now instead.
+    FocusLost,
+}
+
+/// A physical input an action can be bound to. Gamepad bindings match any gamepad.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(String),
+    MouseButton(String),
+    GamepadButton(String),
+}
+
+/// An axis from -1 to 1, made from buttons on either side and/or a gamepad stick.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct AxisBinding {
+    pub negative: Vec<Binding>,
+    pub positive: Vec<Binding>,
+    pub gamepad_axis: Option<String>,
+    /// Stick values smaller than this are ignored
+    pub dead_zone: f32,
+}
+
+/// Named actions and axes, so systems can ask about "jump" rather than the space bar. Loaded from
+/// the project's `input.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct InputMap {
+    pub actions: HashMap<String, Vec<Binding>>,
+    pub axes: HashMap<String, AxisBinding>,
+}
+
+impl InputMap {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+/// The state of the keyboard, mouse and gamepads this tick. Systems can read it with
+/// `tick.get_state::<Input>()`.
+#[derive(Debug, Clone, Default)]
+pub struct Input {
+    pub map: InputMap,
+    held: HashSet<Binding>,
+    just_pressed: HashSet<Binding>,
+    just_released: HashSet<Binding>,
+    /// Per gamepad, by axis name
+    gamepad_axes: HashMap<(usize, String), f32>,
+    /// Gamepad buttons are held per gamepad, but bindings match any of them
+    gamepad_buttons: HashSet<(usize, String)>,
+    mouse_position: Vec2,
+    mouse_delta: Vec2,
+    wheel_delta: f32,
+}
+
+impl Input {
+    pub fn new(map: InputMap) -> Self {
+        Self {
+            map,
+            ..Default::default()
+        }
+    }
+
+    /// Start a new tick with the input that arrived since the last one.
+    pub(crate) fn update(&mut self, events: &[InputEvent]) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.mouse_delta = Vec2::ZERO;
+        self.wheel_delta = 0.;
+
+        for event in events {
+            match event {
+                InputEvent::KeyPressed(key) => self.press(Binding::Key(key.clone())),
+                InputEvent::KeyReleased(key) => self.release(Binding::Key(key.clone())),
+                InputEvent::MouseMoved { x, y } => {
+                    let position = Vec2::new(*x, *y);
+                    self.mouse_delta += position - self.mouse_position;
+                    self.mouse_position = position;
+                }
+                InputEvent::MouseButtonPressed(button) => {
+                    self.press(Binding::MouseButton(button.clone()))
+                }
+                InputEvent::MouseButtonReleased(button) => {
+                    self.release(Binding::MouseButton(button.clone()))
+                }
+                InputEvent::MouseWheel { delta } => self.wheel_delta += delta,
+                InputEvent::GamepadButtonPressed { gamepad, button } => {
+                    self.gamepad_buttons.insert((*gamepad, button.clone()));
+                    self.press(Binding::GamepadButton(button.clone()));
+                }
+                InputEvent::GamepadButtonReleased { gamepad, button } => {
+                    self.release_gamepad_button(*gamepad, button)
+                }
+                InputEvent::GamepadAxis {
+                    gamepad,
+                    axis,
+                    value,
+                } => {
+                    self.gamepad_axes.insert((*gamepad, axis.clone()), *value);
+                }
+                InputEvent::GamepadDisconnected { gamepad } => {
+                    self.gamepad_axes.retain(|(g, _), _| g != gamepad);
+                    let buttons: Vec<String> = self
+                        .gamepad_buttons
+                        .iter()
+                        .filter(|(g, _)| g == gamepad)
+                        .map(|(_, button)| button.clone())
+                        .collect();
+                    for button in buttons {
+                        self.release_gamepad_button(*gamepad, &button);
+                    }
+                }
+                InputEvent::FocusLost => {
+                    let held: Vec<Binding> = self
+                        .held
+                        .iter()
+                        .filter(|binding| !matches!(binding, Binding::GamepadButton(_)))
+                        .cloned()
+                        .collect();
+                    for binding in held {
+                        self.release(binding);
+                    }
+                }
+            }
+        }
+    }
+
+    fn press(&mut self, binding: Binding) {
+        // Held keys repeat; that's not a new press
+        if self.held.insert(binding.clone()) {
+            self.just_pressed.insert(binding);
+        }
+    }
+
+    fn release(&mut self, binding: Binding) {
+        if self.held.remove(&binding) {
+            self.just_released.insert(binding);
+        }
+    }
+
+    fn release_gamepad_button(&mut self, gamepad: usize, button: &str) {
+        self.gamepad_buttons.remove(&(gamepad, button.to_string()));
+        // Someone else might still be holding it
+        if !self.gamepad_buttons.iter().any(|(_, b)| b == button) {
+            self.release(Binding::GamepadButton(button.to_string()));
+        }
+    }
+
+    pub fn is_held(&self, binding: &Binding) -> bool {
+        self.held.contains(binding)
+    }
+
+    pub fn was_just_pressed(&self, binding: &Binding) -> bool {
+        self.just_pressed.contains(binding)
+    }
+
+    pub fn was_just_released(&self, binding: &Binding) -> bool {
+        self.just_released.contains(binding)
+    }
+
+    /// Whether a key is held, by name (eg. "Space", "KeyW", "ArrowLeft").
+    pub fn key_held(&self, key: &str) -> bool {
+        self.is_held(&Binding::Key(key.into()))
+    }
+
+    pub fn mouse_position(&self) -> Vec2 {
+        self.mouse_position
+    }
+
+    /// How far the mouse moved this tick.
+    pub fn mouse_delta(&self) -> Vec2 {
+        self.mouse_delta
+    }
+
+    pub fn wheel_delta(&self) -> f32 {
+        self.wheel_delta
+    }
+
+    /// The most pushed any gamepad's axis is, from -1 to 1.
+    pub fn gamepad_axis(&self, axis: &str) -> f32 {
+        self.gamepad_axes
+            .iter()
+            .filter(|((_, name), _)| name == axis)
+            .map(|(_, value)| *value)
+            .fold(0., |most: f32, value| {
+                if value.abs() > most.abs() {
+                    value
+                } else {
+                    most
+                }
+            })
+    }
+
+    pub fn action_held(&self, action: &str) -> bool {
+        self.bindings(action).any(|binding| self.is_held(binding))
+    }
+
+    pub fn action_just_pressed(&self, action: &str) -> bool {
+        self.bindings(action)
+            .any(|binding| self.was_just_pressed(binding))
+    }
+
+    pub fn action_just_released(&self, action: &str) -> bool {
+        self.bindings(action)
+            .any(|binding| self.was_just_released(binding))
+    }
+
+    /// An axis from the map, from -1 to 1. Zero if there's no such axis.
+    pub fn axis(&self, axis: &str) -> f32 {
+        let Some(binding) = self.map.axes.get(axis) else {
+            return 0.;
+        };
+
+        let held = |bindings: &[Binding]| bindings.iter().any(|b| self.is_held(b)) as i32 as f32;
+        let buttons = held(&binding.positive) - held(&binding.negative);
+
+        let stick = binding
+            .gamepad_axis
+            .as_deref()
+            .map(|axis| self.gamepad_axis(axis))
+            .filter(|value| value.abs() > binding.dead_zone)
+            .unwrap_or_default();
+
+        (buttons + stick).clamp(-1., 1.)
+    }
+
+    fn bindings(&self, action: &str) -> impl Iterator<Item = &Binding> {
+        self.map.actions.get(action).into_iter().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_actions_and_axes() {
+        let map: InputMap = serde_json::from_str(
+            r#"{
+                "actions": { "jump": [{ "Key": "Space" }, { "GamepadButton": "South" }] },
+                "axes": {
+                    "move_x": {
+                        "negative": [{ "Key": "KeyA" }],
+                        "positive": [{ "Key": "KeyD" }],
+                        "gamepad_axis": "LeftStickX",
+                        "dead_zone": 0.2
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let mut input = Input::new(map);
+
+        input.update(&[
+            InputEvent::KeyPressed("Space".into()),
+            InputEvent::KeyPressed("KeyA".into()),
+        ]);
+        assert!(input.action_just_pressed("jump"));
+        assert!(input.action_held("jump"));
+        assert_eq!(input.axis("move_x"), -1.);
+
+        // Held keys repeat, which isn't another press
+        input.update(&[InputEvent::KeyPressed("Space".into())]);
+        assert!(!input.action_just_pressed("jump"));
+        assert!(input.action_held("jump"));
+
+        input.update(&[
+            InputEvent::KeyReleased("Space".into()),
+            InputEvent::KeyReleased("KeyA".into()),
+            InputEvent::GamepadAxis {
+                gamepad: 0,
+                axis: "LeftStickX".into(),
+                value: 0.1,
+            },
+        ]);
+        assert!(input.action_just_released("jump"));
+        assert!(!input.action_held("jump"));
+        // Inside the dead zone
+        assert_eq!(input.axis("move_x"), 0.);
+
+        input.update(&[
+            InputEvent::GamepadAxis {
+                gamepad: 0,
+                axis: "LeftStickX".into(),
+                value: 0.5,
+            },
+            InputEvent::MouseMoved { x: 10., y: 5. },
+            InputEvent::MouseMoved { x: 12., y: 4. },
+        ]);
+        assert_eq!(input.axis("move_x"), 0.5);
+        assert_eq!(input.mouse_position(), Vec2::new(12., 4.));
+        assert_eq!(input.mouse_delta(), Vec2::new(12., 4.));
+        assert!(!input.action_just_released("jump"));
+
+        // Losing focus lets go of keys, but gamepads carry on until they're unplugged
+        input.update(&[
+            InputEvent::KeyPressed("KeyD".into()),
+            InputEvent::GamepadButtonPressed {
+                gamepad: 1,
+                button: "South".into(),
+            },
+        ]);
+        input.update(&[InputEvent::FocusLost]);
+        assert!(!input.key_held("KeyD"));
+        assert!(input.action_held("jump"));
+        assert_eq!(input.axis("move_x"), 0.5);
+
+        input.update(&[InputEvent::GamepadDisconnected { gamepad: 0 }]);
+        assert_eq!(input.axis("move_x"), 0.);
+        assert!(input.action_held("jump"));
+        input.update(&[InputEvent::GamepadDisconnected { gamepad: 1 }]);
+        assert!(input.action_just_released("jump"));
+    }
+}
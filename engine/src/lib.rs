@@ -1,37 +1,115 @@
 use hecs::CommandBuffer;
 use lazy_vulkan::{IntoExtent, LazyVulkan, StateFamily, ash::vk};
+use serde::{Serialize, de::DeserializeOwned};
 use std::{
-    any::{self, Any, TypeId},
     collections::HashMap,
     ffi::CStr,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
+    time::{Instant, SystemTime},
 };
 
-pub use engine_types::components;
+pub use engine_types::{ComponentRegistry, SceneSettings, SystemError, components};
 
-use crate::sub_renderers::SceneRenderer;
+use crate::{
+    changes::ChangeTrackers,
+    events::Events,
+    reload::ReloadableStates,
+    replay::{Recorder, Replay},
+    save_game::{SaveGames, SaveRequest},
+    schedule::{FixedSteps, Schedule, SystemContext},
+    sub_renderers::SceneRenderer,
+    system_errors::SystemErrors,
+    time::Clock,
+};
+mod changes;
+pub mod conditions;
+mod events;
+pub mod hierarchy;
+mod input;
+mod prefabs;
+mod profiler;
+mod reload;
+mod replay;
+mod rng;
+mod save_game;
+mod schedule;
+mod state_manager;
 mod sub_renderers;
+mod system_errors;
+mod time;
+mod timers;
+
+pub use changes::{AddHook, Changes, RemoveHook};
+pub use input::{AxisBinding, Binding, Input, InputEvent, InputMap};
+pub use prefabs::{Prefabs, SpawnedPrefab, compile_prefabs, load_definitions};
+pub use profiler::{ProfileScope, Profiler, Timing};
+pub use replay::{RecordedFrame, WorldHashFn};
+pub use rng::Rng;
+pub use save_game::{SaveGame, SaveMetadata};
+pub use schedule::{RunCondition, RunMode, Stage, System, SystemDescriptor};
+pub use state_manager::{StateManager, States};
+pub use system_errors::ErrorPolicy;
+pub use time::Time;
+pub use timers::{Cooldown, Timers};
 
-type StateMap = HashMap<TypeId, Box<dyn Any>>;
+type SceneResourceFn = Box<dyn Fn(serde_json::Value, &mut StateManager) -> anyhow::Result<()>>;
 
 pub struct Engine {
-    systems: HashMap<String, SystemFn>,
+    systems: Schedule,
+    system_errors: SystemErrors,
+    events: Events,
+    profiler: Profiler,
     state: StateManager,
+    reloadable_states: ReloadableStates,
     world: hecs::World,
-    lazy_vulkan: LazyVulkan<TickDataFamily>,
+    scene_resources: HashMap<String, SceneResourceFn>,
+    clock: Clock,
+    /// Input pushed since the last tick
+    pending_input: Vec<InputEvent>,
+    /// Where each tick's [`Rng`] seed comes from
+    seeds: Rng,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+    /// Where the last replay diverged, kept once it's finished
+    finished_replay_divergence: Option<u64>,
+    /// Turns components into JSON and back, for save games
+    component_registry: ComponentRegistry,
+    save_games: SaveGames,
+    change_trackers: ChangeTrackers,
+    /// The project's prefabs, for systems to spawn
+    prefabs: Prefabs,
+    /// `None` when simulating, without a GPU
+    lazy_vulkan: Option<LazyVulkan<TickDataFamily>>,
     #[allow(unused)]
     project_path: PathBuf,
 }
 
 pub const VERSION: &str = git_version::git_version!();
 pub type SystemFn = fn(&mut TickData) -> anyhow::Result<()>;
+pub type ExclusiveSystemFn = fn(&mut ExclusiveTickData) -> anyhow::Result<()>;
 
 pub struct TickData<'a> {
     pub dt: f32,
     pub command_buffer: CommandBuffer,
     pub world: &'a hecs::World,
     state: &'a mut StateManager,
+    events: &'a Events,
+    profiler: &'a Profiler,
+    prefabs: &'a Prefabs,
+    component_registry: &'a ComponentRegistry,
+    /// The running system, which is who reads events
+    system: &'a str,
+}
+
+/// What an exclusive system gets: the whole world, mutably, and all of the state.
+pub struct ExclusiveTickData<'a> {
+    pub dt: f32,
+    pub world: &'a mut hecs::World,
+    pub state: &'a mut StateManager,
+    events: &'a Events,
+    profiler: &'a Profiler,
+    system: &'a str,
 }
 
 struct TickDataFamily;
@@ -43,14 +121,143 @@ impl<'a> TickData<'a> {
     pub fn get_state<S: 'static>(&mut self) -> Result<&mut S, EngineError> {
         self.state.get_state()
     }
+
+    /// Several different states at once, eg. `tick.get_states::<(Score, Level)>()`.
+    pub fn get_states<'s, S: States<'s>>(&'s mut self) -> Result<S::Refs, EngineError> {
+        self.state.get_states::<S>()
+    }
+
+    pub fn get_or_insert_state_with<S: Send + 'static>(&mut self, f: impl FnOnce() -> S) -> &mut S {
+        self.state.get_or_insert_with(f)
+    }
+
+    pub fn contains_state<S: 'static>(&self) -> bool {
+        self.state.contains_state::<S>()
+    }
+
+    pub fn remove_state<S: 'static>(&mut self) -> Option<S> {
+        self.state.remove_state()
+    }
+
+    /// Send an event. It can be read for the rest of this frame and all of the next.
+    pub fn send_event<E: Clone + Send + 'static>(&self, event: E) {
+        self.events.send(event);
+    }
+
+    /// Every `E` sent since this system last read them, oldest first.
+    pub fn read_events<E: Clone + Send + 'static>(&self) -> Vec<E> {
+        self.events.read(self.system)
+    }
+
+    /// Time part of a system, until the returned guard is dropped.
+    pub fn profile<'s>(&'s self, name: &'s str) -> ProfileScope<'s> {
+        self.profiler.scope("gameplay", name)
+    }
+
+    /// The first entity called `name`. See [`hierarchy`] for more ways to find things.
+    pub fn find_by_name(&self, name: &str) -> Option<hecs::Entity> {
+        hierarchy::find_by_name(self.world, name)
+    }
+
+    /// An entity by its path within a prefab instance, eg. `Player/Weapon/Muzzle`.
+    pub fn find_by_path(&self, path: &str) -> Option<hecs::Entity> {
+        hierarchy::find_by_path(self.world, path)
+    }
+
+    /// Shorthand for `tick.get_state::<Timers>()`.
+    pub fn timers(&mut self) -> Result<&mut Timers, EngineError> {
+        self.state.get_state()
+    }
+
+    /// Save the game to a slot once this tick's systems have run. See [`Engine::save_game`].
+    pub fn save_game(&self, slot: usize, level: impl Into<String>) {
+        self.events.send(SaveRequest::Save {
+            slot,
+            level: level.into(),
+        });
+    }
+
+    /// Load the save in a slot once this tick's systems have run. See [`Engine::load_game`].
+    pub fn load_game(&self, slot: usize) {
+        self.events.send(SaveRequest::Load { slot });
+    }
+
+    /// Spawn a prefab from the project's `prefabs` directory, with its root at `transform`. Like
+    /// anything else in the command buffer, the entities exist once this system has finished.
+    pub fn spawn_prefab(
+        &mut self,
+        name: &str,
+        transform: components::Transform,
+    ) -> Result<SpawnedPrefab, EngineError> {
+        let prefab = self
+            .prefabs
+            .get(name)
+            .ok_or_else(|| EngineError::UnknownPrefab(name.to_string()))?;
+        Ok(prefabs::spawn(
+            prefab,
+            transform,
+            self.world,
+            self.component_registry,
+            &mut self.command_buffer,
+        ))
+    }
+
+    /// Despawn a prefab instance: `root`, and everything under it.
+    pub fn despawn_prefab(&mut self, root: hecs::Entity) {
+        prefabs::despawn(root, self.world, &mut self.command_buffer);
+    }
+}
+
+impl<'a> ExclusiveTickData<'a> {
+    pub fn send_event<E: Clone + Send + 'static>(&self, event: E) {
+        self.events.send(event);
+    }
+
+    pub fn read_events<E: Clone + Send + 'static>(&self) -> Vec<E> {
+        self.events.read(self.system)
+    }
+
+    pub fn profile<'s>(&'s self, name: &'s str) -> ProfileScope<'s> {
+        self.profiler.scope("gameplay", name)
+    }
+}
+
+/// States copied by [`Engine::state_snapshot`].
+pub struct StateSnapshot {
+    time: Time,
+    timers: Timers,
+    states: HashMap<String, serde_json::Value>,
 }
 
 #[derive(Debug, Clone)]
 pub enum EngineError {
     BadPointer,
     UninitialisedState(&'static str),
+    /// The same state was asked for twice at once
+    DuplicateState(&'static str),
+    /// These systems' `before`/`after` constraints contradict each other
+    SystemCycle(Vec<String>),
+    /// There's no prefab with this name, or it couldn't be compiled
+    UnknownPrefab(String),
+}
+
+impl std::fmt::Display for EngineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EngineError::BadPointer => write!(f, "Bad engine pointer"),
+            EngineError::UninitialisedState(name) => write!(f, "State {name} hasn't been inserted"),
+            EngineError::DuplicateState(name) => write!(f, "State {name} was asked for twice"),
+            EngineError::SystemCycle(systems) => {
+                write!(f, "Systems can't be ordered: {}", systems.join(" -> "))
+            }
+            EngineError::UnknownPrefab(name) => write!(f, "There's no prefab called {name}"),
+        }
+    }
 }
 
+// So systems can use `?` on states
+impl std::error::Error for EngineError {}
+
 impl Engine {
     pub fn new_headless(
         project_path: impl Into<PathBuf>,
@@ -64,10 +271,46 @@ impl Engine {
         let scene_renderer = SceneRenderer::new(&mut lazy_vulkan, project_path.join("assets"));
         lazy_vulkan.add_sub_renderer(Box::new(scene_renderer));
 
+        Self::new(project_path, Some(lazy_vulkan))
+    }
+
+    /// An engine with no renderer at all, for tests, servers and machines without a GPU.
+    /// Ticking runs systems as usual, and skips drawing.
+    pub fn new_simulation(project_path: impl Into<PathBuf>) -> Engine {
+        Self::new(project_path.into(), None)
+    }
+
+    fn new(project_path: PathBuf, lazy_vulkan: Option<LazyVulkan<TickDataFamily>>) -> Engine {
+        let mut state = StateManager::default();
+        state.insert_state(Time::default());
+        state.insert_state(Input::new(load_input_map(&project_path)));
+        state.insert_state(Timers::default());
+        state.insert_state(Changes::default());
+
         Engine {
             systems: Default::default(),
-            state: Default::default(),
+            system_errors: Default::default(),
+            events: Default::default(),
+            profiler: Default::default(),
+            state,
+            reloadable_states: Default::default(),
             world: Default::default(),
+            scene_resources: Default::default(),
+            clock: Default::default(),
+            pending_input: Default::default(),
+            seeds: Rng::new(
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|d| d.as_nanos() as u64)
+                    .unwrap_or_default(),
+            ),
+            recorder: None,
+            replay: None,
+            finished_replay_divergence: None,
+            component_registry: engine_components(),
+            save_games: SaveGames::new(&project_path),
+            change_trackers: Default::default(),
+            prefabs: Prefabs::load(&project_path.join("prefabs")),
             lazy_vulkan,
             project_path,
         }
@@ -83,60 +326,314 @@ impl Engine {
         unsafe { ptr.as_mut().ok_or(EngineError::BadPointer) }
     }
 
+    /// Register a system in [`Stage::Update`], replacing any existing system with that name.
     pub fn register_system(&mut self, name: impl Into<String>, system: SystemFn) {
-        self.systems.insert(name.into(), system);
+        if let Err(e) = self.add_system(SystemDescriptor::new(name, system)) {
+            log::error!("Unable to register system: {e:?}");
+        }
+    }
+
+    /// Register an exclusive system in [`Stage::Update`], replacing any existing system with that
+    /// name.
+    pub fn register_exclusive_system(
+        &mut self,
+        name: impl Into<String>,
+        system: ExclusiveSystemFn,
+    ) {
+        if let Err(e) = self.add_system(SystemDescriptor::exclusive(name, system)) {
+            log::error!("Unable to register system: {e:?}");
+        }
+    }
+
+    /// Register a system with a stage and ordering constraints, replacing any existing system
+    /// with that name. Fails if the constraints can't be satisfied.
+    pub fn add_system(&mut self, descriptor: SystemDescriptor) -> Result<(), EngineError> {
+        let name = descriptor.name.clone();
+        self.systems.add(descriptor)?;
+        // New code deserves a fresh start
+        self.system_errors.forget(&name);
+        Ok(())
+    }
+
+    /// What to do when a system fails, unless it has a policy of its own.
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.system_errors.policy = policy;
+    }
+
+    /// The most recent errors from systems, oldest first.
+    pub fn system_errors(&self) -> &[SystemError] {
+        self.system_errors.recent()
+    }
+
+    pub fn clear_system_errors(&mut self) {
+        self.system_errors.clear();
+    }
+
+    /// Switch a system on or off. This sticks, even across hot reloads.
+    pub fn set_system_enabled(&mut self, name: &str, enabled: bool) {
+        self.systems.set_system_enabled(name, enabled);
+    }
+
+    /// Switch every system in a group on or off. This sticks, even across hot reloads.
+    pub fn set_group_enabled(&mut self, group: &str, enabled: bool) {
+        self.systems.set_group_enabled(group, enabled);
+    }
+
+    /// Let systems that panicked run again. Called when gameplay code is hot reloaded.
+    pub fn clear_faults(&mut self) {
+        self.system_errors.clear_faults();
+    }
+
+    /// Start running a system that was disabled by its error policy, or by panicking, again.
+    pub fn enable_system(&mut self, name: &str) {
+        self.system_errors.forget(name);
     }
 
     pub fn tick_headless(&mut self, run_systems: bool) {
-        let command_buffer = CommandBuffer::new();
-        let mut tick_data = TickData {
-            dt: 0.,
-            state: &mut self.state,
-            world: &self.world,
-            command_buffer,
-        };
+        let real_delta = self.clock.tick();
+        self.update(real_delta, run_systems, |lazy_vulkan, tick_data| {
+            let drawable = lazy_vulkan.get_drawable();
+            lazy_vulkan.draw_to_drawable(tick_data, &drawable);
+        });
+    }
+
+    pub fn tick(&mut self) {
+        let real_delta = self.clock.tick();
+        self.update(real_delta, true, |lazy_vulkan, tick_data| {
+            lazy_vulkan.draw(tick_data)
+        });
+    }
 
-        if run_systems {
-            for (system_name, system) in &mut self.systems {
-                log::trace!("[{system_name}] system starting..");
-                if let Err(e) = system(&mut tick_data) {
-                    log::error!("[{system_name}]: {e:?}");
-                    return;
-                }
-                log::trace!("[{system_name}] system complete");
+    /// Tick as though `real_delta` seconds had passed, rather than measuring it. Handy for tests
+    /// and servers that keep their own time.
+    pub fn step(&mut self, real_delta: f32) {
+        self.update(real_delta, true, |lazy_vulkan, tick_data| {
+            lazy_vulkan.draw(tick_data)
+        });
+    }
+
+    fn update(
+        &mut self,
+        real_delta: f32,
+        playing: bool,
+        draw: impl FnOnce(&mut LazyVulkan<TickDataFamily>, &TickData),
+    ) {
+        let start = Instant::now();
+        let frame = self.next_frame(real_delta, playing);
+        // Time doesn't pass for the game while the editor has it stopped
+        let (dt, fixed) = self.advance_time(frame.real_delta, !frame.playing);
+        // Timers wait for the game too, as do their delayed commands
+        if frame.playing {
+            self.advance_timers(dt);
+        }
+        self.state.insert_state(Rng::new(frame.seed));
+        self.state
+            .get_or_insert_with(Input::default)
+            .update(&frame.inputs);
+        for event in &frame.inputs {
+            self.events.send(event.clone());
+        }
+        self.prefabs.compile_if_stale(&self.component_registry);
+        // Editor systems still run while it's stopped
+        self.run_systems(dt, fixed, frame.playing);
+        self.handle_save_requests();
+        self.detect_changes();
+        self.finish_frame(frame);
+
+        if let Some(lazy_vulkan) = &mut self.lazy_vulkan {
+            // The renderer only reads, so this command buffer is always empty
+            let tick_data = TickData {
+                dt,
+                command_buffer: CommandBuffer::new(),
+                world: &self.world,
+                state: &mut self.state,
+                events: &self.events,
+                profiler: &self.profiler,
+                prefabs: &self.prefabs,
+                component_registry: &self.component_registry,
+                system: "renderer",
+            };
+            let draw_start = Instant::now();
+            draw(lazy_vulkan, &tick_data);
+            self.profiler
+                .record("renderer", "draw", draw_start, draw_start.elapsed());
+        }
+
+        self.profiler.record("tick", "tick", start, start.elapsed());
+    }
+
+    /// Everything that goes into this tick: live, or from the replay if there is one.
+    fn next_frame(&mut self, real_delta: f32, playing: bool) -> RecordedFrame {
+        if let Some(replay) = &mut self.replay {
+            // Live input would make the replay diverge
+            self.pending_input.clear();
+            if let Some(frame) = replay.next_frame() {
+                return frame;
             }
+            log::info!("Replay finished");
+            self.finished_replay_divergence = replay.diverged_at();
+            self.replay = None;
         }
 
-        let drawable = self.lazy_vulkan.get_drawable();
-        self.lazy_vulkan.draw_to_drawable(&tick_data, &drawable);
+        RecordedFrame {
+            real_delta,
+            playing,
+            seed: self.seeds.next_u64(),
+            inputs: std::mem::take(&mut self.pending_input),
+            world_hash: None,
+        }
+    }
 
-        tick_data.command_buffer.run_on(&mut self.world);
+    /// Find what happened to tracked components this tick, for hooks now and systems next tick.
+    fn detect_changes(&mut self) {
+        let _scope = self.profiler.scope("engine", "detect_changes");
+        let changes = self
+            .change_trackers
+            .update(&mut self.world, &mut self.state);
+        self.state.insert_state(changes);
     }
 
-    pub fn tick(&mut self) {
-        let command_buffer = CommandBuffer::new();
-        let mut tick_data = TickData {
-            dt: 0.,
+    /// Watch for `C` being added, changed and removed. See [`Changes`], including for what it
+    /// costs every tick.
+    pub fn track_changes<C: hecs::Component + Clone + PartialEq>(&mut self) {
+        self.change_trackers.track::<C>();
+    }
+
+    /// Call `hook` whenever `C` is added to an entity. Hooks run after each tick's systems.
+    pub fn on_add<C: hecs::Component + Clone + PartialEq>(&mut self, hook: AddHook) {
+        self.change_trackers.on_add::<C>(hook);
+    }
+
+    /// Call `hook` whenever `C` is removed from an entity, or it's despawned.
+    pub fn on_remove<C: hecs::Component + Clone + PartialEq>(&mut self, hook: RemoveHook<C>) {
+        self.change_trackers.on_remove::<C>(hook);
+    }
+
+    /// Save or load for systems that asked to this tick.
+    fn handle_save_requests(&mut self) {
+        for request in self.events.read::<SaveRequest>("save_games") {
+            let result = match request.clone() {
+                SaveRequest::Save { slot, level } => self.save_game(slot, level),
+                SaveRequest::Load { slot } => self.load_game(slot).map(|_| ()),
+            };
+            if let Err(e) = result {
+                log::error!("Unable to handle {request:?}: {e:?}");
+            }
+        }
+    }
+
+    /// Record the frame, or check it against the replay, once its systems have run.
+    fn finish_frame(&mut self, frame: RecordedFrame) {
+        if let Some(replay) = &mut self.replay {
+            replay.check(frame.world_hash, &self.world, &self.component_registry);
+        } else if let Some(recorder) = &mut self.recorder
+            && let Err(e) = recorder.record(frame, &self.world, &self.component_registry)
+        {
+            log::error!("Unable to record frame, stopping recording: {e:?}");
+            self.recorder = None;
+        }
+    }
+
+    /// Queue up input for systems, which they'll get as [`InputEvent`]s next tick.
+    pub fn push_input(&mut self, event: InputEvent) {
+        self.pending_input.push(event);
+    }
+
+    /// Record every tick's delta, input and random seed to `path`, so the session can be
+    /// replayed exactly. With a `hash`, the world is hashed each tick too so a replay can tell
+    /// when it's diverged.
+    pub fn start_recording(
+        &mut self,
+        path: impl AsRef<Path>,
+        hash: Option<WorldHashFn>,
+    ) -> anyhow::Result<()> {
+        self.recorder = Some(Recorder::create(path.as_ref(), hash)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.recorder = None;
+    }
+
+    /// Replay a recording from [`Engine::start_recording`], starting next tick. Live input and
+    /// the real time are ignored until it's finished. The world should be in the same state it
+    /// was when recording started.
+    pub fn start_replay(
+        &mut self,
+        path: impl AsRef<Path>,
+        hash: Option<WorldHashFn>,
+    ) -> anyhow::Result<()> {
+        self.replay = Some(Replay::load(path.as_ref(), hash)?);
+        self.finished_replay_divergence = None;
+        Ok(())
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// The first frame of the replay where the world didn't match the recording, if any. Still
+    /// there once the replay's finished, until the next one starts.
+    pub fn replay_divergence(&self) -> Option<u64> {
+        match &self.replay {
+            Some(replay) => replay.diverged_at(),
+            None => self.finished_replay_divergence,
+        }
+    }
+
+    /// Per-system, per-renderer-stage and per-tick timings. Start a trace here to record a
+    /// session for `chrome://tracing` or Perfetto.
+    pub fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+
+    fn run_systems(&mut self, dt: f32, fixed: FixedSteps, playing: bool) {
+        let context = SystemContext {
+            world: &mut self.world,
             state: &mut self.state,
-            world: &self.world,
-            command_buffer,
+            events: &self.events,
+            profiler: &self.profiler,
+            prefabs: &self.prefabs,
+            component_registry: &self.component_registry,
+            errors: &mut self.system_errors,
+            playing,
         };
+        self.systems.run(context, dt, fixed);
+        self.events.update();
+    }
 
-        for (system_name, system) in &mut self.systems {
-            log::trace!("[{system_name}] system starting..");
-            if let Err(e) = system(&mut tick_data) {
-                log::error!("[{system_name}]: {e:?}");
-                return;
-            }
-            log::trace!("[{system_name}] system complete");
+    /// Send an event to systems from outside of them.
+    pub fn send_event<E: Clone + Send + 'static>(&self, event: E) {
+        self.events.send(event);
+    }
+
+    /// Advance [`Time`] by `real_delta` seconds.
+    fn advance_time(&mut self, real_delta: f32, paused: bool) -> (f32, FixedSteps) {
+        // Someone may have replaced it; make sure there's always a clock
+        if self.state.get_state::<Time>().is_err() {
+            self.state.insert_state(Time::default());
         }
+        let time = self.state.get_state::<Time>().unwrap();
 
-        self.lazy_vulkan.draw(&tick_data);
+        let steps = time.advance(real_delta, paused);
+        let fixed = FixedSteps {
+            steps,
+            timestep: time.fixed_timestep,
+        };
 
-        tick_data.command_buffer.run_on(&mut self.world);
+        (time.delta, fixed)
     }
 
-    pub fn insert_state<S: 'static>(&mut self, state: S) {
+    /// Count down [`Timers`] and [`Cooldown`]s, and run delayed commands that are due.
+    fn advance_timers(&mut self, dt: f32) {
+        let due = self.state.get_or_insert_with(Timers::default).advance(dt);
+        for mut commands in due {
+            commands.run_on(&mut self.world);
+        }
+        timers::advance_cooldowns(&mut self.world, dt);
+    }
+
+    pub fn insert_state<S: Send + 'static>(&mut self, state: S) {
         self.state.insert_state(state);
     }
 
@@ -144,39 +641,614 @@ impl Engine {
         self.state.get_state()
     }
 
+    pub fn contains_state<S: 'static>(&self) -> bool {
+        self.state.contains_state::<S>()
+    }
+
+    pub fn remove_state<S: 'static>(&mut self) -> Option<S> {
+        self.state.remove_state()
+    }
+
+    /// Insert state that survives hot reloads, under a name that stays the same between
+    /// builds. Call this from both `init` and `reload`: after a reload the saved copy is
+    /// restored instead of `state`.
+    pub fn insert_reloadable_state<S: Serialize + DeserializeOwned + Send + 'static>(
+        &mut self,
+        name: impl Into<String>,
+        state: S,
+    ) {
+        self.reloadable_states
+            .insert(name.into(), state, &mut self.state);
+    }
+
+    /// Copy [`Time`], [`Timers`] and every reloadable state, to put back with
+    /// [`Engine::restore_state_snapshot`]. Other states can't be copied, so they're left as
+    /// they are. Neither can delayed commands, so they're not kept.
+    pub fn state_snapshot(&mut self) -> StateSnapshot {
+        StateSnapshot {
+            time: self.get_state::<Time>().cloned().unwrap_or_default(),
+            timers: self
+                .state
+                .get::<Timers>()
+                .map(Timers::without_delayed)
+                .unwrap_or_default(),
+            states: self.reloadable_states.snapshot(&self.state),
+        }
+    }
+
+    /// Put states back the way they were when `snapshot` was taken. Events that haven't been
+    /// read yet belong to the moment being thrown away, so they're dropped too, as are any
+    /// delayed commands.
+    pub fn restore_state_snapshot(&mut self, snapshot: StateSnapshot) {
+        self.state.insert_state(snapshot.time);
+        self.state.insert_state(snapshot.timers);
+        self.reloadable_states
+            .restore(snapshot.states, &mut self.state);
+        self.events = Events::default();
+    }
+
+    /// Like [`Engine::insert_reloadable_state`], and the state is written to save games too.
+    pub fn insert_persistent_state<S: Serialize + DeserializeOwned + Send + 'static>(
+        &mut self,
+        name: impl Into<String>,
+        state: S,
+    ) {
+        let name = name.into();
+        self.save_games.states.insert(name.clone());
+        self.insert_reloadable_state(name, state);
+    }
+
+    /// Components need to be registered here to be saved, or used in prefabs. The engine's own
+    /// are already.
+    pub fn component_registry_mut(&mut self) -> &mut ComponentRegistry {
+        // Prefabs might use whatever's registered next
+        self.prefabs.invalidate();
+        &mut self.component_registry
+    }
+
+    pub fn component_registry(&self) -> &ComponentRegistry {
+        &self.component_registry
+    }
+
+    /// Bump this when the game's components or persistent states change in ways old saves
+    /// can't be loaded into.
+    pub fn set_save_version(&mut self, version: u32) {
+        self.save_games.version = version;
+    }
+
+    /// Save persistent entities and states to `saves/slot_N.json` in the project.
+    pub fn save_game(&mut self, slot: usize, level: impl Into<String>) -> anyhow::Result<()> {
+        let playtime = self.get_state::<Time>().map(|time| time.elapsed)?;
+        let states = self
+            .reloadable_states
+            .snapshot(&self.state)
+            .into_iter()
+            .filter(|(name, _)| self.save_games.states.contains(name))
+            .collect();
+
+        let entities = save_game::save_world(&self.world, &self.component_registry);
+        let save = SaveGame {
+            version: self.save_games.version,
+            metadata: SaveMetadata {
+                timestamp: save_game::now(),
+                level: level.into(),
+                playtime,
+            },
+            entity_refs: save_game::save_entity_refs(
+                &self.world,
+                &self.component_registry,
+                &entities,
+            ),
+            entities,
+            states,
+        };
+
+        let path = self.save_games.slot_path(slot);
+        save.write(&path)?;
+        log::info!("Saved to {path:?}");
+        Ok(())
+    }
+
+    /// Replace the persisted entities and persistent states with a save's. Entities without any
+    /// persistent components are left as they are.
+    pub fn load_game(&mut self, slot: usize) -> anyhow::Result<SaveMetadata> {
+        let path = self.save_games.slot_path(slot);
+        let save = SaveGame::read(&path)?;
+        if save.version != self.save_games.version {
+            anyhow::bail!(
+                "{path:?} is from save version {}, but this is version {}",
+                save.version,
+                self.save_games.version
+            );
+        }
+
+        save_game::load_world(
+            save.entities,
+            &save.entity_refs,
+            &mut self.world,
+            &self.component_registry,
+        )?;
+        self.reloadable_states
+            .restore(save.states.into_iter().collect(), &mut self.state);
+        self.get_state::<Time>()?.elapsed = save.metadata.playtime;
+
+        log::info!("Loaded {path:?}");
+        Ok(save.metadata)
+    }
+
+    /// Every slot with a save in it, in order, with what's known about each save.
+    pub fn save_slots(&self) -> Vec<(usize, SaveMetadata)> {
+        self.save_games.slots()
+    }
+
+    /// Called just before gameplay code is hot reloaded, while the old library is still loaded.
+    ///
+    /// Reloadable states are saved, and everything else that might point into the old library
+    /// (states, systems, events and scene resources) is dropped. The new library is expected
+    /// to register it all again in `reload`. Components in the world are left alone, as are
+    /// the scene's settings, so [`Engine::reload_scene_resources`] can load its resources again.
+    pub fn prepare_for_reload(&mut self) {
+        self.reloadable_states.save(&self.state);
+
+        // Our own states might have been boxed by gameplay code, so box them again here
+        let time = self.state.remove_state::<Time>().unwrap_or_default();
+        let settings = self.state.remove_state::<SceneSettings>();
+        let input = self.state.remove_state::<Input>().unwrap_or_default();
+        let mut timers = self.state.remove_state::<Timers>().unwrap_or_default();
+        timers.drop_delayed();
+        self.state = StateManager::default();
+        self.state.insert_state(time);
+        self.state.insert_state(input);
+        self.state.insert_state(timers);
+        self.state.insert_state(Changes::default());
+        if let Some(settings) = settings {
+            self.state.insert_state(settings);
+        }
+
+        self.systems.clear();
+        self.events = Events::default();
+        self.scene_resources.clear();
+        // Gameplay code may have registered its own components
+        self.component_registry = engine_components();
+        self.prefabs.invalidate();
+        self.save_games.states.clear();
+        // Hooks and tracked types too. Everything looks newly added after the reload
+        self.change_trackers.clear();
+        self.system_errors.clear_faults();
+    }
+
+    /// Scenes can carry a resource of this type in their settings, under `name`. It's inserted
+    /// as state when the scene's settings are loaded.
+    pub fn register_scene_resource<R: serde::de::DeserializeOwned + Send + 'static>(
+        &mut self,
+        name: impl Into<String>,
+    ) {
+        self.scene_resources.insert(
+            name.into(),
+            Box::new(|value, state| {
+                let resource: R = serde_json::from_value(value)?;
+                state.insert_state(resource);
+                Ok(())
+            }),
+        );
+    }
+
+    /// Insert a scene's settings as state, along with any registered resources it carries.
+    pub fn load_scene_settings(&mut self, settings: &SceneSettings) {
+        for (name, value) in &settings.resources {
+            let Some(load) = self.scene_resources.get(name) else {
+                log::warn!("Scene resource {name} hasn't been registered, skipping");
+                continue;
+            };
+
+            if let Err(e) = load(value.clone(), &mut self.state) {
+                log::error!("Unable to load scene resource {name}: {e:?}");
+            }
+        }
+
+        self.state.insert_state(settings.clone());
+    }
+
+    /// Load the current scene's resources again, from its settings. Hot reloading drops them
+    /// with the old library, so call this once the new one has registered them again. Any
+    /// changes made to them while running are lost.
+    pub fn reload_scene_resources(&mut self) {
+        let Some(settings) = self.state.get::<SceneSettings>().cloned() else {
+            return;
+        };
+        self.load_scene_settings(&settings);
+    }
+
+    pub fn world(&self) -> &hecs::World {
+        &self.world
+    }
+
     pub fn world_mut(&mut self) -> &mut hecs::World {
         &mut self.world
     }
 
     pub fn get_headless_image(&self) -> lazy_vulkan::HeadlessSwapchainImage {
         self.lazy_vulkan
+            .as_ref()
+            .expect("There's no renderer when simulating")
             .renderer
             .get_headless_image()
             .expect("You're not in headless mode, idiot")
     }
 
     pub fn resize(&mut self, new_extent: impl IntoExtent) {
-        self.lazy_vulkan.resize(new_extent);
+        if let Some(lazy_vulkan) = &mut self.lazy_vulkan {
+            lazy_vulkan.resize(new_extent);
+        }
     }
 }
 
-#[derive(Debug, Default)]
-struct StateManager {
-    inner: StateMap,
+/// What's always in the engine's component registry, even after a hot reload: the shared
+/// components, and the engine's own.
+fn engine_components() -> ComponentRegistry {
+    let mut registry = ComponentRegistry::default();
+    registry.register_component::<Cooldown>();
+    registry
 }
 
-impl StateManager {
-    pub fn get_state<S: 'static>(&mut self) -> Result<&mut S, EngineError> {
-        // heheeheh
-        self.inner
-            .get_mut(&TypeId::of::<S>())
-            .map(|s| s.downcast_mut())
-            .flatten()
-            .ok_or_else(|| EngineError::UninitialisedState(any::type_name::<S>()))
+/// The project's action and axis bindings, if it has any.
+fn load_input_map(project_path: &Path) -> InputMap {
+    let path = project_path.join("input.json");
+    if !path.exists() {
+        return InputMap::default();
+    }
+
+    InputMap::load(&path).unwrap_or_else(|e| {
+        log::error!("Unable to load input map from {path:?}: {e:?}");
+        InputMap::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_and_count(tick: &mut TickData) -> anyhow::Result<()> {
+        tick.command_buffer.spawn((1_u32,));
+        *tick.get_state::<usize>()? += 1;
+        Ok(())
+    }
+
+    #[test]
+    fn test_simulation() {
+        let mut engine = Engine::new_simulation(".");
+        engine.insert_state(0_usize);
+        engine.register_system("spawn_and_count", spawn_and_count);
+
+        engine.step(0.1);
+        engine.step(0.1);
+
+        assert_eq!(*engine.get_state::<usize>().unwrap(), 2);
+        assert_eq!(engine.world().len(), 2);
+
+        let time = engine.get_state::<Time>().unwrap();
+        assert_eq!(time.frame_count, 2);
+        assert!((time.elapsed - 0.2).abs() < 1e-6);
+    }
+
+    fn spawn_from_input(tick: &mut TickData) -> anyhow::Result<()> {
+        for event in tick.read_events::<InputEvent>() {
+            if event == InputEvent::KeyPressed("Space".into()) {
+                let offset = tick.get_state::<Rng>()?.range(0.0..10.0);
+                tick.command_buffer.spawn((offset,));
+            }
+        }
+        Ok(())
+    }
+
+    fn sum_offsets(world: &hecs::World, _: &ComponentRegistry) -> u64 {
+        world
+            .query::<&f32>()
+            .iter()
+            .map(|(_, offset)| offset.to_bits() as u64)
+            .sum()
+    }
+
+    fn offsets(engine: &Engine) -> Vec<f32> {
+        let mut offsets = engine
+            .world()
+            .query::<&f32>()
+            .iter()
+            .map(|(_, offset)| *offset)
+            .collect::<Vec<_>>();
+        offsets.sort_by(f32::total_cmp);
+        offsets
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!("replay-{}.jsonl", std::process::id()));
+
+        let mut engine = Engine::new_simulation(".");
+        engine.register_system("spawn_from_input", spawn_from_input);
+        engine
+            .start_recording(&path, Some(Box::new(sum_offsets)))
+            .unwrap();
+        for delta in [0.1, 0.2, 0.05] {
+            engine.push_input(InputEvent::KeyPressed("Space".into()));
+            engine.step(delta);
+        }
+        engine.stop_recording();
+        let recorded = offsets(&engine);
+        assert_eq!(recorded.len(), 3);
+
+        // A fresh engine, with different seeds and no input of its own
+        let mut engine = Engine::new_simulation(".");
+        engine.register_system("spawn_from_input", spawn_from_input);
+        engine
+            .start_replay(&path, Some(Box::new(sum_offsets)))
+            .unwrap();
+        for _ in 0..3 {
+            engine.step(1.0);
+        }
+        assert_eq!(offsets(&engine), recorded);
+        assert_eq!(engine.replay_divergence(), None);
+        assert!((engine.get_state::<Time>().unwrap().elapsed - 0.35).abs() < 1e-6);
+
+        // Out of frames, so back to live
+        engine.step(1.0);
+        assert!(!engine.is_replaying());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_divergence_outlives_replay() {
+        let path = std::env::temp_dir().join(format!("diverge-{}.jsonl", std::process::id()));
+
+        let mut engine = Engine::new_simulation(".");
+        engine.register_system("spawn_from_input", spawn_from_input);
+        engine
+            .start_recording(&path, Some(Box::new(sum_offsets)))
+            .unwrap();
+        for delta in [0.1, 0.2] {
+            engine.push_input(InputEvent::KeyPressed("Space".into()));
+            engine.step(delta);
+        }
+        engine.stop_recording();
+
+        // Without the system nothing spawns, so the very first frame diverges
+        let mut engine = Engine::new_simulation(".");
+        engine
+            .start_replay(&path, Some(Box::new(sum_offsets)))
+            .unwrap();
+        for _ in 0..3 {
+            engine.step(1.0);
+        }
+        assert!(!engine.is_replaying());
+        assert_eq!(engine.replay_divergence(), Some(0));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[derive(Serialize, serde::Deserialize)]
+    struct Coins(u32);
+
+    #[test]
+    fn test_timers_wait_while_stopped() {
+        let mut engine = Engine::new_simulation(".");
+        engine.get_state::<Timers>().unwrap().start("respawn", 0.5);
+        let cooldown = engine.world_mut().spawn((Cooldown::new(0.5),));
+
+        engine.update(1.0, false, |_, _| {});
+        assert!(engine.get_state::<Timers>().unwrap().is_running("respawn"));
+        assert!(
+            !engine
+                .world()
+                .get::<&Cooldown>(cooldown)
+                .unwrap()
+                .is_ready()
+        );
+
+        engine.update(1.0, true, |_, _| {});
+        assert!(
+            engine
+                .get_state::<Timers>()
+                .unwrap()
+                .just_finished("respawn")
+        );
+        assert!(
+            engine
+                .world()
+                .get::<&Cooldown>(cooldown)
+                .unwrap()
+                .is_ready()
+        );
+
+        // Cooldowns are registered, and stay that way after a hot reload
+        engine.prepare_for_reload();
+        assert!(
+            engine
+                .component_registry()
+                .get_type_id("Cooldown")
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_state_snapshot() {
+        let mut engine = Engine::new_simulation(".");
+        engine.insert_state(Timers::default());
+        engine.get_state::<Timers>().unwrap().start("respawn", 1.0);
+        let snapshot = engine.state_snapshot();
+
+        let timers = engine.get_state::<Timers>().unwrap();
+        timers.cancel("respawn");
+        timers.start("explode", 1.0);
+        timers.after(0.5, hecs::CommandBuffer::new());
+        engine.restore_state_snapshot(snapshot);
+
+        // The timers are back how they were, and the delayed commands gone
+        let timers = engine.get_state::<Timers>().unwrap();
+        assert!(timers.is_running("respawn"));
+        assert!(!timers.is_running("explode"));
+        assert!(timers.advance(1.0).is_empty());
     }
 
-    pub fn insert_state<S: 'static>(&mut self, state: S) {
-        let type_id = TypeId::of::<S>();
-        self.inner.insert(type_id, Box::new(state));
+    #[test]
+    fn test_scene_resources_survive_reload() {
+        let mut engine = Engine::new_simulation(".");
+        engine.register_scene_resource::<Coins>("coins");
+        let mut settings = SceneSettings::default();
+        settings
+            .resources
+            .insert("coins".into(), serde_json::json!(7));
+        engine.load_scene_settings(&settings);
+        engine.get_state::<Coins>().unwrap().0 = 8;
+
+        engine.prepare_for_reload();
+        assert!(!engine.contains_state::<Coins>());
+
+        // Once the new library has registered it again, it's loaded from the scene
+        engine.register_scene_resource::<Coins>("coins");
+        engine.reload_scene_resources();
+        assert_eq!(engine.get_state::<Coins>().unwrap().0, 7);
+        assert_eq!(engine.get_state::<SceneSettings>().unwrap(), &settings);
+    }
+
+    fn checkpoint(tick: &mut TickData) -> anyhow::Result<()> {
+        tick.save_game(3, "level_1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let project = std::env::temp_dir().join(format!("saves-{}", std::process::id()));
+
+        let mut engine = Engine::new_simulation(&project);
+        engine.insert_persistent_state("coins", Coins(10));
+        engine.insert_reloadable_state("not_saved", 1_usize);
+        let player = engine
+            .world_mut()
+            .spawn((components::Transform::default(), 5_u32));
+        engine.register_system("checkpoint", checkpoint);
+        engine.step(1.0);
+        engine.set_system_enabled("checkpoint", false);
+
+        // Things change after the checkpoint..
+        engine.get_state::<Coins>().unwrap().0 = 0;
+        *engine.get_state::<usize>().unwrap() = 2;
+        engine
+            .world_mut()
+            .spawn((components::Transform::default(),));
+        engine.world_mut().despawn(player).unwrap();
+
+        // ..and loading puts them back
+        let metadata = engine.load_game(3).unwrap();
+        assert_eq!(metadata.level, "level_1");
+        assert_eq!(engine.save_slots(), vec![(3, metadata)]);
+        assert_eq!(engine.get_state::<Coins>().unwrap().0, 10);
+        assert_eq!(*engine.get_state::<usize>().unwrap(), 2);
+        assert_eq!(engine.world().len(), 1);
+        // Unregistered components aren't saved
+        assert!(engine.world().get::<&components::Transform>(player).is_ok());
+        assert!(engine.world().get::<&u32>(player).is_err());
+
+        // Saves from other versions don't load
+        engine.set_save_version(2);
+        assert!(engine.load_game(3).is_err());
+
+        std::fs::remove_dir_all(project).unwrap();
+    }
+
+    /// A component that refers to another entity, for tests.
+    #[derive(Serialize, serde::Deserialize, Clone)]
+    pub(crate) struct Follow(pub engine_types::EntityRef);
+
+    impl engine_types::CanYak for Follow {
+        fn get_paint_fn() -> engine_types::PaintFn {
+            Box::new(|_, _| {})
+        }
+    }
+
+    impl engine_types::MapEntities for Follow {
+        fn map_entities(&mut self, map: &mut dyn FnMut(&mut engine_types::EntityRef)) {
+            self.0.map_entities(map);
+        }
+    }
+
+    #[test]
+    fn test_load_keeps_other_entities() {
+        let project = std::env::temp_dir().join(format!("save-refs-{}", std::process::id()));
+
+        let mut engine = Engine::new_simulation(&project);
+        let registry = engine.component_registry_mut();
+        registry.register_component::<Follow>();
+        registry.register_entity_mapper::<Follow>();
+
+        let leader = engine
+            .world_mut()
+            .spawn((components::Transform::default(),));
+        let follow = Follow(engine_types::EntityRef::new(
+            engine_types::NodeID::new(1),
+            leader,
+        ));
+        let follower = engine.world_mut().spawn((follow,));
+        let ghost = engine.world_mut().spawn((1_u32,));
+        engine.save_game(0, "level_1").unwrap();
+
+        // The leader's ID goes to something that isn't saved
+        engine.world_mut().despawn(leader).unwrap();
+        let usurper = engine.world_mut().spawn((2_u32,));
+        assert_eq!(usurper.id(), leader.id());
+
+        // Entities that weren't saved are left alone, and the leader gets a new ID which the
+        // follower follows
+        engine.load_game(0).unwrap();
+        assert_eq!(engine.world().len(), 4);
+        assert_eq!(*engine.world().get::<&u32>(ghost).unwrap(), 1);
+        assert_eq!(*engine.world().get::<&u32>(usurper).unwrap(), 2);
+        let leader = engine.world().get::<&Follow>(follower).unwrap().0.entity();
+        let leader = leader.unwrap();
+        assert_ne!(leader, usurper);
+        assert!(engine.world().get::<&components::Transform>(leader).is_ok());
+
+        std::fs::remove_dir_all(project).unwrap();
+    }
+
+    #[test]
+    fn test_load_keeps_hierarchy() {
+        let project = std::env::temp_dir().join(format!("save-parents-{}", std::process::id()));
+
+        let mut engine = Engine::new_simulation(&project);
+        let player = engine.world_mut().spawn((
+            components::Transform::default(),
+            components::Name("Player".into()),
+        ));
+        let weapon = engine.world_mut().spawn((
+            components::Transform::default(),
+            components::Name("Weapon".into()),
+            components::Parent(player),
+        ));
+        // Its parent isn't saved, so it's gone by the time the save is loaded
+        let anchor = engine.world_mut().spawn((1_u32,));
+        let stray = engine
+            .world_mut()
+            .spawn((components::Transform::default(), components::Parent(anchor)));
+        engine.save_game(0, "level_1").unwrap();
+
+        // The player's ID goes to something that isn't saved
+        engine.world_mut().despawn(anchor).unwrap();
+        engine.world_mut().despawn(player).unwrap();
+        let usurper = engine.world_mut().spawn((2_u32,));
+        assert_eq!(usurper.id(), player.id());
+
+        engine.load_game(0).unwrap();
+        let world = engine.world();
+        let player = world.get::<&components::Parent>(weapon).unwrap().0;
+        assert_ne!(player, usurper);
+        assert_eq!(world.get::<&components::Name>(player).unwrap().0, "Player");
+        assert_eq!(
+            hierarchy::find_by_path(world, "Player/Weapon"),
+            Some(weapon)
+        );
+        assert!(world.get::<&components::Parent>(stray).is_err());
+
+        std::fs::remove_dir_all(project).unwrap();
     }
 }
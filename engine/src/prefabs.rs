@@ -0,0 +1,278 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Context;
+use engine_types::{
+    ComponentRegistry, Prefab, PrefabDefinition,
+    components::{Parent, Transform},
+};
+use hecs::{CommandBuffer, Entity};
+
+/// The project's prefabs, so systems can spawn them at runtime. Definitions are loaded once;
+/// they're compiled with the engine's [`ComponentRegistry`] before the next tick whenever it
+/// might have changed, since compiled prefabs hold on to code from whoever registered their
+/// components.
+#[derive(Default)]
+pub struct Prefabs {
+    definitions: HashMap<String, PrefabDefinition>,
+    compiled: HashMap<String, Prefab>,
+    stale: bool,
+}
+
+/// The entities a prefab was spawned as, in the same order as its nodes. They exist once the
+/// system's commands have been applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnedPrefab {
+    pub root: Entity,
+    pub nodes: Vec<Entity>,
+}
+
+impl Prefabs {
+    pub(crate) fn load(directory: &Path) -> Self {
+        let definitions = if directory.exists() {
+            load_definitions(directory).unwrap_or_else(|e| {
+                log::error!("Unable to load prefabs from {directory:?}: {e:?}");
+                Default::default()
+            })
+        } else {
+            Default::default()
+        };
+
+        Self {
+            definitions,
+            compiled: Default::default(),
+            stale: true,
+        }
+    }
+
+    /// Compile them all again before the next tick.
+    pub(crate) fn invalidate(&mut self) {
+        self.compiled.clear();
+        self.stale = true;
+    }
+
+    pub(crate) fn compile_if_stale(&mut self, component_registry: &ComponentRegistry) {
+        if !self.stale {
+            return;
+        }
+        self.stale = false;
+        self.compiled = compile_prefabs(&self.definitions, component_registry);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.compiled.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.definitions.keys().map(String::as_str)
+    }
+}
+
+/// Read every prefab definition in `directory`, keyed by file name.
+pub fn load_definitions(directory: &Path) -> anyhow::Result<HashMap<String, PrefabDefinition>> {
+    let mut definitions = HashMap::new();
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let reader = std::fs::File::open(&path)?;
+        let definition = serde_json::from_reader(std::io::BufReader::new(reader))
+            .with_context(|| format!("Unable to parse prefab {path:?}"))?;
+        definitions.insert(name.to_string(), definition);
+    }
+
+    Ok(definitions)
+}
+
+/// Compile every definition that only uses components `component_registry` knows about. The
+/// rest are skipped, with an error.
+pub fn compile_prefabs(
+    definitions: &HashMap<String, PrefabDefinition>,
+    component_registry: &ComponentRegistry,
+) -> HashMap<String, Prefab> {
+    let mut compiled = HashMap::new();
+    for (name, definition) in definitions {
+        // The compiler can't cope with components it doesn't know about
+        if let Some(unknown) = unknown_component(definition, component_registry) {
+            log::error!("Prefab {name} uses {unknown}, which isn't registered, skipping it");
+            continue;
+        }
+
+        let prefab = prefab_compiler::compile(definition, component_registry);
+        compiled.insert(name.clone(), prefab);
+    }
+    compiled
+}
+
+fn unknown_component<'a>(
+    definition: &'a PrefabDefinition,
+    component_registry: &ComponentRegistry,
+) -> Option<&'a str> {
+    definition
+        .components
+        .keys()
+        .find(|name| component_registry.get_type_id(name).is_none())
+        .map(String::as_str)
+        .or_else(|| {
+            definition
+                .children
+                .iter()
+                .find_map(|child| unknown_component(child, component_registry))
+        })
+}
+
+/// Reserve an entity for each of the prefab's nodes, and queue up their components. The root is
+/// put at `transform`; the rest keep the transforms from the prefab. [`EntityRef`]s between the
+/// prefab's nodes point at the new entities.
+///
+/// [`EntityRef`]: engine_types::EntityRef
+pub(crate) fn spawn(
+    prefab: &Prefab,
+    transform: Transform,
+    world: &hecs::World,
+    component_registry: &ComponentRegistry,
+    command_buffer: &mut CommandBuffer,
+) -> SpawnedPrefab {
+    let nodes: Vec<Entity> = prefab
+        .nodes
+        .iter()
+        .map(|_| world.reserve_entity())
+        .collect();
+
+    // Components are built here first, so their references can be resolved before they're queued
+    let mut scratch = hecs::World::new();
+    for (node, entity) in prefab.nodes.iter().zip(&nodes) {
+        // Nodes without a transform of their own get a default one, like in the editor
+        command_buffer.insert(*entity, (Transform::default(),));
+
+        let built = scratch.spawn(&node.builder);
+        // The prefab refers to its own nodes by index
+        component_registry.map_entity_refs(&scratch, built, &mut |component_name, entity_ref| {
+            let index = entity_ref.node_id().as_raw();
+            let target = nodes.get(index).copied();
+            if target.is_none() {
+                log::warn!(
+                    "{component_name} in prefab {} refers to node {index}, which it doesn't have",
+                    prefab.name
+                );
+            }
+            entity_ref.resolve(entity_ref.node_id(), target);
+        });
+        command_buffer.insert(*entity, scratch.take(built).unwrap());
+
+        if let Some(parent) = node.parent.and_then(|index| nodes.get(index)) {
+            command_buffer.insert(*entity, (Parent(*parent),));
+        }
+    }
+
+    let root = nodes[0];
+    command_buffer.insert(root, (transform,));
+
+    SpawnedPrefab { root, nodes }
+}
+
+/// Queue up despawning `root` and everything under it.
+pub(crate) fn despawn(root: Entity, world: &hecs::World, command_buffer: &mut CommandBuffer) {
+    let mut remaining = vec![root];
+    while let Some(entity) = remaining.pop() {
+        remaining.extend(crate::hierarchy::children(world, entity));
+        command_buffer.despawn(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Follow;
+    use engine_types::components::Name;
+
+    #[test]
+    fn test_spawn_and_despawn() {
+        let definition = serde_json::from_value(serde_json::json!({
+            "name": "Player",
+            "components": { "Transform": { "position": [0., 1., 0.], "rotation": [0., 0., 0., 1.] } },
+            "children": [{
+                "name": "Weapon",
+                "components": {},
+                "children": [{ "name": "Muzzle", "components": {} }]
+            }]
+        }))
+        .unwrap();
+        let mut prefabs = Prefabs {
+            definitions: [("player".to_string(), definition)].into(),
+            ..Default::default()
+        };
+        prefabs.invalidate();
+        prefabs.compile_if_stale(&ComponentRegistry::default());
+
+        let mut world = hecs::World::new();
+        let other = world.spawn((Transform::default(),));
+        let mut command_buffer = CommandBuffer::new();
+        let transform = Transform {
+            position: glam::Vec3::X,
+            ..Default::default()
+        };
+        let spawned = spawn(
+            prefabs.get("player").unwrap(),
+            transform.clone(),
+            &world,
+            &ComponentRegistry::default(),
+            &mut command_buffer,
+        );
+        command_buffer.run_on(&mut world);
+
+        assert_eq!(world.len(), 4);
+        assert_eq!(*world.get::<&Transform>(spawned.root).unwrap(), transform);
+        let muzzle = crate::hierarchy::find_by_path(&world, "Player/Weapon/Muzzle").unwrap();
+        assert_eq!(muzzle, spawned.nodes[2]);
+        assert_eq!(world.get::<&Name>(muzzle).unwrap().0, "Muzzle");
+
+        despawn(spawned.root, &world, &mut command_buffer);
+        command_buffer.run_on(&mut world);
+        assert_eq!(world.len(), 1);
+        assert!(world.contains(other));
+    }
+
+    #[test]
+    fn test_spawn_entity_refs() {
+        let mut component_registry = ComponentRegistry::default();
+        component_registry.register_component::<Follow>();
+        component_registry.register_entity_mapper::<Follow>();
+
+        // The pet follows its owner, the prefab's root
+        let definition = serde_json::from_value(serde_json::json!({
+            "name": "Owner",
+            "components": {},
+            "children": [{ "name": "Pet", "components": { "Follow": 0 } }]
+        }))
+        .unwrap();
+        let definitions = [("owner".to_string(), definition)].into();
+        let prefabs = compile_prefabs(&definitions, &component_registry);
+
+        let mut world = hecs::World::new();
+        let mut command_buffer = CommandBuffer::new();
+        let mut spawn_owner = |world: &mut hecs::World| {
+            let spawned = spawn(
+                &prefabs["owner"],
+                Transform::default(),
+                world,
+                &component_registry,
+                &mut command_buffer,
+            );
+            command_buffer.run_on(world);
+            spawned
+        };
+        let first = spawn_owner(&mut world);
+        let second = spawn_owner(&mut world);
+
+        // Each pet follows its own owner
+        for spawned in [first, second] {
+            let follow = world.get::<&Follow>(spawned.nodes[1]).unwrap();
+            assert_eq!(follow.0.entity(), Some(spawned.root));
+        }
+    }
+}
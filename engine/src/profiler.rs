@@ -0,0 +1,221 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    path::Path,
+    sync::{Mutex, MutexGuard, PoisonError},
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+use serde_json::json;
+
+/// How many of the most recent samples the rolling statistics cover.
+const WINDOW: usize = 120;
+
+/// Times systems, renderer stages and ticks. Statistics are always kept; a Chrome trace (which
+/// Perfetto can open too) is only recorded between [`Profiler::start_trace`] and
+/// [`Profiler::write_trace`].
+pub struct Profiler {
+    epoch: Instant,
+    // Parallel systems report in at the same time
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// By category, then name
+    samples: BTreeMap<&'static str, HashMap<String, Samples>>,
+    trace: Option<Vec<TraceEvent>>,
+    /// Chrome traces want small numbers for threads
+    threads: HashMap<ThreadId, usize>,
+}
+
+#[derive(Default)]
+struct Samples {
+    recent: VecDeque<Duration>,
+    count: u64,
+}
+
+struct TraceEvent {
+    category: &'static str,
+    name: String,
+    start: Duration,
+    duration: Duration,
+    thread: usize,
+}
+
+/// Rolling statistics for one thing being timed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timing {
+    pub category: &'static str,
+    pub name: String,
+    pub last: Duration,
+    /// Over the last few seconds' worth of samples
+    pub mean: Duration,
+    pub max: Duration,
+    /// Every sample, ever
+    pub count: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+            inner: Default::default(),
+        }
+    }
+}
+
+impl Profiler {
+    /// Time until the returned guard is dropped.
+    pub fn scope<'a>(&'a self, category: &'static str, name: &'a str) -> ProfileScope<'a> {
+        ProfileScope {
+            profiler: self,
+            category,
+            name,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn record(&self, category: &'static str, name: &str, start: Instant, duration: Duration) {
+        let mut inner = self.lock();
+
+        let samples = inner.samples.entry(category).or_default();
+        if !samples.contains_key(name) {
+            samples.insert(name.to_string(), Samples::default());
+        }
+        let samples = samples.get_mut(name).unwrap();
+        if samples.recent.len() == WINDOW {
+            samples.recent.pop_front();
+        }
+        samples.recent.push_back(duration);
+        samples.count += 1;
+
+        if inner.trace.is_none() {
+            return;
+        }
+
+        let next_thread = inner.threads.len();
+        let thread = *inner
+            .threads
+            .entry(thread::current().id())
+            .or_insert(next_thread);
+        let start = start.saturating_duration_since(self.epoch);
+        if let Some(trace) = &mut inner.trace {
+            trace.push(TraceEvent {
+                category,
+                name: name.to_string(),
+                start,
+                duration,
+                thread,
+            });
+        }
+    }
+
+    /// Statistics for everything timed so far, by category and then name.
+    pub fn timings(&self) -> Vec<Timing> {
+        let inner = self.lock();
+        let mut timings = Vec::new();
+
+        for (&category, samples) in &inner.samples {
+            for (name, samples) in samples {
+                let Some(last) = samples.recent.back() else {
+                    continue;
+                };
+                let total: Duration = samples.recent.iter().sum();
+
+                timings.push(Timing {
+                    category,
+                    name: name.clone(),
+                    last: *last,
+                    mean: total / samples.recent.len() as u32,
+                    max: samples.recent.iter().max().copied().unwrap_or_default(),
+                    count: samples.count,
+                });
+            }
+        }
+
+        timings.sort_by(|a, b| (a.category, &a.name).cmp(&(b.category, &b.name)));
+        timings
+    }
+
+    /// Start recording a trace, throwing away any trace in progress.
+    pub fn start_trace(&self) {
+        self.lock().trace = Some(Vec::new());
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.lock().trace.is_some()
+    }
+
+    /// Stop recording, and write what was recorded to `path` in the Chrome trace format.
+    pub fn write_trace(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let events = self.lock().trace.take().unwrap_or_default();
+        let events: Vec<_> = events
+            .iter()
+            .map(|event| {
+                json!({
+                    "name": event.name,
+                    "cat": event.category,
+                    "ph": "X",
+                    "ts": event.start.as_secs_f64() * 1_000_000.,
+                    "dur": event.duration.as_secs_f64() * 1_000_000.,
+                    "pid": 1,
+                    "tid": event.thread,
+                })
+            })
+            .collect();
+
+        let trace = json!({ "traceEvents": events, "displayTimeUnit": "ms" });
+        std::fs::write(path, serde_json::to_string(&trace)?)?;
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Records the time since it was created when dropped.
+pub struct ProfileScope<'a> {
+    profiler: &'a Profiler,
+    category: &'static str,
+    name: &'a str,
+    start: Instant,
+}
+
+impl Drop for ProfileScope<'_> {
+    fn drop(&mut self) {
+        self.profiler
+            .record(self.category, self.name, self.start, self.start.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timings() {
+        let profiler = Profiler::default();
+        let start = Instant::now();
+        profiler.record("system", "movement", start, Duration::from_millis(2));
+        profiler.record("system", "movement", start, Duration::from_millis(4));
+        profiler.record("tick", "tick", start, Duration::from_millis(10));
+
+        let timings = profiler.timings();
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings[0].name, "movement");
+        assert_eq!(timings[0].last, Duration::from_millis(4));
+        assert_eq!(timings[0].mean, Duration::from_millis(3));
+        assert_eq!(timings[0].max, Duration::from_millis(4));
+        assert_eq!(timings[0].count, 2);
+
+        // Nothing's traced until asked for
+        assert!(!profiler.is_tracing());
+        profiler.start_trace();
+        {
+            let _scope = profiler.scope("system", "movement");
+        }
+        assert_eq!(profiler.lock().trace.as_ref().unwrap().len(), 1);
+    }
+}
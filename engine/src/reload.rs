@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use crate::StateManager;
+
+type SaveStateFn = Box<dyn Fn(&StateManager) -> anyhow::Result<Option<Value>>>;
+type LoadStateFn = Box<dyn Fn(Value, &mut StateManager) -> anyhow::Result<()>>;
+
+/// States that survive a hot reload. They're serialised by name before the old library is
+/// unloaded, since neither their vtables nor their `TypeId`s can be trusted afterwards, and
+/// deserialised when the new library inserts them again.
+#[derive(Default)]
+pub(crate) struct ReloadableStates {
+    save_fns: HashMap<String, SaveStateFn>,
+    load_fns: HashMap<String, LoadStateFn>,
+    /// Serialised copies left behind by the last library
+    saved: HashMap<String, Value>,
+}
+
+impl ReloadableStates {
+    /// Insert `state`, or the copy saved under `name` by the last library if there is one.
+    pub fn insert<S: Serialize + DeserializeOwned + Send + 'static>(
+        &mut self,
+        name: String,
+        state: S,
+        manager: &mut StateManager,
+    ) {
+        let state = match self.saved.remove(&name).map(serde_json::from_value::<S>) {
+            Some(Ok(restored)) => {
+                log::debug!("Restored {name} from before the reload");
+                restored
+            }
+            Some(Err(e)) => {
+                log::warn!("Unable to restore {name} after the reload, resetting it: {e:?}");
+                state
+            }
+            None => state,
+        };
+        manager.insert_state(state);
+
+        self.save_fns.insert(
+            name.clone(),
+            Box::new(|manager: &StateManager| {
+                manager
+                    .get::<S>()
+                    .map(serde_json::to_value)
+                    .transpose()
+                    .map_err(Into::into)
+            }),
+        );
+        self.load_fns.insert(
+            name,
+            Box::new(|value, manager: &mut StateManager| {
+                manager.insert_state(serde_json::from_value::<S>(value)?);
+                Ok(())
+            }),
+        );
+    }
+
+    /// Serialise every reloadable state, and forget how to, since that knowledge lives in the
+    /// library that's about to go away.
+    pub fn save(&mut self, manager: &StateManager) {
+        self.saved = self.snapshot(manager);
+        self.save_fns.clear();
+        self.load_fns.clear();
+    }
+
+    /// Serialise every reloadable state, by name.
+    pub fn snapshot(&self, manager: &StateManager) -> HashMap<String, Value> {
+        let mut snapshot = HashMap::new();
+        for (name, save) in &self.save_fns {
+            match save(manager) {
+                Ok(Some(value)) => {
+                    snapshot.insert(name.clone(), value);
+                }
+                Ok(None) => log::debug!("{name} was removed, not saving it"),
+                Err(e) => log::error!("Unable to save {name}, it'll be reset: {e:?}"),
+            }
+        }
+        snapshot
+    }
+
+    /// Put states back the way they were in `snapshot`.
+    pub fn restore(&self, snapshot: HashMap<String, Value>, manager: &mut StateManager) {
+        for (name, value) in snapshot {
+            let Some(load) = self.load_fns.get(&name) else {
+                log::warn!("{name} isn't reloadable any more, not restoring it");
+                continue;
+            };
+
+            if let Err(e) = load(value, manager) {
+                log::error!("Unable to restore {name}: {e:?}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Score(u32);
+
+    #[test]
+    fn test_reload() {
+        let mut reloadable = ReloadableStates::default();
+        let mut manager = StateManager::default();
+        reloadable.insert("score".into(), Score(0), &mut manager);
+        manager.get_state::<Score>().unwrap().0 = 12;
+
+        // The old library goes away, taking its states with it..
+        reloadable.save(&manager);
+        let mut manager = StateManager::default();
+
+        // ..and the new one gets them back
+        reloadable.insert("score".into(), Score(0), &mut manager);
+        assert_eq!(manager.get_state::<Score>().unwrap(), &Score(12));
+
+        // Only once though
+        let mut manager = StateManager::default();
+        reloadable.insert("score".into(), Score(0), &mut manager);
+        assert_eq!(manager.get_state::<Score>().unwrap(), &Score(0));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut reloadable = ReloadableStates::default();
+        let mut manager = StateManager::default();
+        reloadable.insert("score".into(), Score(3), &mut manager);
+
+        let snapshot = reloadable.snapshot(&manager);
+        manager.get_state::<Score>().unwrap().0 = 12;
+        reloadable.restore(snapshot, &mut manager);
+        assert_eq!(manager.get_state::<Score>().unwrap(), &Score(3));
+    }
+}
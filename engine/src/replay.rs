@@ -0,0 +1,115 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use engine_types::ComponentRegistry;
+
+use crate::InputEvent;
+
+/// Hashes the world, to check a replay hasn't diverged from its recording. It's given the
+/// engine's registry each frame, so components gameplay code registers later are hashed too. See
+/// [`ComponentRegistry::hash_world`].
+pub type WorldHashFn = Box<dyn Fn(&hecs::World, &ComponentRegistry) -> u64>;
+
+/// Everything that went into one tick. Recordings are one of these per line, as JSON.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedFrame {
+    pub real_delta: f32,
+    /// Whether the game was playing, or stopped in the editor
+    pub playing: bool,
+    pub seed: u64,
+    pub inputs: Vec<InputEvent>,
+    /// The world after the tick's systems ran, if the recording was hashing it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub world_hash: Option<u64>,
+}
+
+pub(crate) struct Recorder {
+    writer: BufWriter<File>,
+    hash: Option<WorldHashFn>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, hash: Option<WorldHashFn>) -> anyhow::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            hash,
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        mut frame: RecordedFrame,
+        world: &hecs::World,
+        component_registry: &ComponentRegistry,
+    ) -> anyhow::Result<()> {
+        frame.world_hash = self
+            .hash
+            .as_ref()
+            .map(|hash| hash(world, component_registry));
+        serde_json::to_writer(&mut self.writer, &frame)?;
+        writeln!(self.writer)?;
+        // The session we want to reproduce may be about to crash
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+pub(crate) struct Replay {
+    frames: VecDeque<RecordedFrame>,
+    hash: Option<WorldHashFn>,
+    frame: u64,
+    diverged_at: Option<u64>,
+}
+
+impl Replay {
+    pub fn load(path: &Path, hash: Option<WorldHashFn>) -> anyhow::Result<Self> {
+        let mut frames = VecDeque::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                frames.push_back(serde_json::from_str(&line)?);
+            }
+        }
+
+        Ok(Self {
+            frames,
+            hash,
+            frame: 0,
+            diverged_at: None,
+        })
+    }
+
+    pub fn next_frame(&mut self) -> Option<RecordedFrame> {
+        self.frames.pop_front()
+    }
+
+    /// Compare the world with the recording after a frame's systems have run.
+    pub fn check(
+        &mut self,
+        expected: Option<u64>,
+        world: &hecs::World,
+        component_registry: &ComponentRegistry,
+    ) {
+        let frame = self.frame;
+        self.frame += 1;
+
+        let (Some(expected), Some(hash)) = (expected, &self.hash) else {
+            return;
+        };
+        if hash(world, component_registry) != expected && self.diverged_at.is_none() {
+            log::error!("Replay diverged from its recording on frame {frame}");
+            self.diverged_at = Some(frame);
+        }
+    }
+
+    /// The first frame the world didn't match the recording, if it's happened.
+    pub fn diverged_at(&self) -> Option<u64> {
+        self.diverged_at
+    }
+}
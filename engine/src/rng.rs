@@ -0,0 +1,49 @@
+use std::ops::Range;
+
+/// A small, fast random number generator (splitmix64). The engine reseeds the one in state
+/// every tick, so sessions can be recorded and replayed exactly; gameplay code should get its
+/// randomness from there.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Between 0 and 1, not including 1.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1_u64 << 24) as f32
+    }
+
+    pub fn range(&mut self, range: Range<f32>) -> f32 {
+        range.start + self.next_f32() * (range.end - range.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..100 {
+            let value = a.range(-1.0..1.0);
+            assert_eq!(value, b.range(-1.0..1.0));
+            assert!((-1.0..1.0).contains(&value));
+        }
+        assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use engine_types::{ComponentRegistry, components::Parent};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Ask the engine to save or load once this tick's systems have run. See
+/// [`crate::TickData::save_game`].
+#[derive(Debug, Clone)]
+pub(crate) enum SaveRequest {
+    Save { slot: usize, level: String },
+    Load { slot: usize },
+}
+
+/// What's shown about a save before it's loaded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SaveMetadata {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub level: String,
+    /// Scaled seconds of play, from [`crate::Time::elapsed`]
+    pub playtime: f64,
+}
+
+/// A save file, as written to `saves/slot_N.json` in the project.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SaveGame {
+    /// The game's save version, from [`crate::Engine::set_save_version`]. Saves from other
+    /// versions won't load.
+    pub version: u32,
+    pub metadata: SaveMetadata,
+    /// Persistent components, by entity ID
+    pub entities: BTreeMap<u64, BTreeMap<String, Value>>,
+    /// Which entity IDs the [`engine_types::EntityRef`]s in each saved entity point to, by
+    /// component, in the order they're mapped. The references themselves are saved as node
+    /// IDs, which can't tell apart entities spawned from the same prefab.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub entity_refs: BTreeMap<u64, BTreeMap<String, Vec<Option<u64>>>>,
+    /// Persistent states, by name
+    pub states: BTreeMap<String, Value>,
+}
+
+impl SaveGame {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        serde_json::from_str(&json).with_context(|| format!("Unable to parse save {path:?}"))
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, engine_types::canonical::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// Where saves go, and which states go in them.
+#[derive(Debug)]
+pub(crate) struct SaveGames {
+    pub directory: PathBuf,
+    pub version: u32,
+    /// The names of reloadable states that are saved too
+    pub states: HashSet<String>,
+}
+
+impl SaveGames {
+    pub fn new(project_path: &Path) -> Self {
+        Self {
+            directory: project_path.join("saves"),
+            version: 1,
+            states: Default::default(),
+        }
+    }
+
+    pub fn slot_path(&self, slot: usize) -> PathBuf {
+        self.directory.join(format!("slot_{slot}.json"))
+    }
+
+    /// Every slot with a save in it, in order.
+    pub fn slots(&self) -> Vec<(usize, SaveMetadata)> {
+        let Ok(entries) = std::fs::read_dir(&self.directory) else {
+            return Vec::new();
+        };
+
+        let mut slots: Vec<(usize, SaveMetadata)> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let slot = path
+                    .file_stem()?
+                    .to_str()?
+                    .strip_prefix("slot_")?
+                    .parse()
+                    .ok()?;
+                match SaveGame::read(&path) {
+                    Ok(save) => Some((slot, save.metadata)),
+                    Err(e) => {
+                        log::warn!("Skipping unreadable save {path:?}: {e:?}");
+                        None
+                    }
+                }
+            })
+            .collect();
+        slots.sort_by_key(|(slot, _)| *slot);
+        slots
+    }
+}
+
+/// Serialise every entity with at least one persistent component.
+pub(crate) fn save_world(
+    world: &hecs::World,
+    component_registry: &ComponentRegistry,
+) -> BTreeMap<u64, BTreeMap<String, Value>> {
+    world
+        .iter()
+        .map(|entity_ref| entity_ref.entity())
+        .map(|entity| {
+            let components = component_registry.serialise_persistent(world, entity);
+            (entity.to_bits().get(), components)
+        })
+        .filter(|(_, components)| !components.is_empty())
+        .collect()
+}
+
+/// Where the references in each saved entity's persistent components point. See
+/// [`SaveGame::entity_refs`].
+pub(crate) fn save_entity_refs(
+    world: &hecs::World,
+    component_registry: &ComponentRegistry,
+    entities: &BTreeMap<u64, BTreeMap<String, Value>>,
+) -> BTreeMap<u64, BTreeMap<String, Vec<Option<u64>>>> {
+    let mut entity_refs = BTreeMap::new();
+    for id in entities.keys() {
+        let Some(entity) = hecs::Entity::from_bits(*id) else {
+            continue;
+        };
+
+        let mut components: BTreeMap<String, Vec<Option<u64>>> = BTreeMap::new();
+        component_registry.map_entity_refs(world, entity, &mut |component_name, entity_ref| {
+            let persistent = component_registry
+                .get_type_id(component_name)
+                .is_some_and(|type_id| component_registry.is_persistent(type_id));
+            if persistent {
+                let target = entity_ref.entity().map(|entity| entity.to_bits().get());
+                components
+                    .entry(component_name.to_string())
+                    .or_default()
+                    .push(target);
+            }
+        });
+
+        if !components.is_empty() {
+            entity_refs.insert(*id, components);
+        }
+    }
+    entity_refs
+}
+
+/// Replace the world's persisted entities (anything with a persistent component) with the saved
+/// ones, leaving everything else alone. Saved entities keep their IDs where they can, so
+/// references to them still work, and references and [`Parent`]s in them are pointed at whatever
+/// they referred to when saved.
+///
+/// Persisted entities are rebuilt from scratch, so anything on them that wasn't saved is lost.
+pub(crate) fn load_world(
+    entities: BTreeMap<u64, BTreeMap<String, Value>>,
+    entity_refs: &BTreeMap<u64, BTreeMap<String, Vec<Option<u64>>>>,
+    world: &mut hecs::World,
+    component_registry: &ComponentRegistry,
+) -> anyhow::Result<()> {
+    let persisted: Vec<hecs::Entity> = world
+        .iter()
+        .filter(|entity_ref| {
+            entity_ref.component_types().any(|type_id| {
+                component_registry.get_name(type_id).is_some()
+                    && component_registry.is_persistent(type_id)
+            })
+        })
+        .map(|entity_ref| entity_ref.entity())
+        .collect();
+    for entity in persisted {
+        world.despawn(entity)?;
+    }
+
+    // Saved entities, and what they are now
+    let mut loaded = HashMap::new();
+    let taken: HashSet<u32> = world
+        .iter()
+        .map(|entity_ref| entity_ref.entity().id())
+        .collect();
+    let mut displaced = Vec::new();
+    for (id, components) in entities {
+        let saved = hecs::Entity::from_bits(id).context("Bad entity ID in save")?;
+        let components = component_registry.build_entity(components);
+        if taken.contains(&saved.id()) {
+            displaced.push((saved, components));
+        } else {
+            world.spawn_at(saved, &components);
+            loaded.insert(saved, saved);
+        }
+    }
+    // Something that wasn't saved has their IDs now, so they make do with new ones. They're
+    // spawned last so they can't take the IDs of the others.
+    for (saved, components) in displaced {
+        loaded.insert(saved, world.spawn(&components));
+    }
+
+    for (saved, entity) in &loaded {
+        let Some(components) = entity_refs.get(&saved.to_bits().get()) else {
+            continue;
+        };
+
+        let mut mapped: HashMap<String, usize> = HashMap::new();
+        component_registry.map_entity_refs(world, *entity, &mut |component_name, entity_ref| {
+            let index = mapped.entry(component_name.to_string()).or_default();
+            let target = components
+                .get(component_name)
+                .and_then(|targets| targets.get(*index))
+                .copied()
+                .flatten()
+                .and_then(hecs::Entity::from_bits)
+                .map(|target| loaded.get(&target).copied().unwrap_or(target))
+                .filter(|target| world.contains(*target));
+            *index += 1;
+            entity_ref.resolve(entity_ref.node_id(), target);
+        });
+    }
+
+    // Parents are saved as the entity they were, which might have moved too
+    let mut orphans = Vec::new();
+    for entity in loaded.values() {
+        let Ok(mut parent) = world.get::<&mut Parent>(*entity) else {
+            continue;
+        };
+        parent.0 = loaded.get(&parent.0).copied().unwrap_or(parent.0);
+        if !world.contains(parent.0) {
+            orphans.push(*entity);
+        }
+    }
+    for entity in orphans {
+        world.remove_one::<Parent>(entity)?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,966 @@
+use std::{
+    any::TypeId,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    ops::ControlFlow,
+    sync::Arc,
+};
+
+use hecs::CommandBuffer;
+use rayon::prelude::*;
+
+use crate::{
+    ComponentRegistry, EngineError, ErrorPolicy, ExclusiveSystemFn, ExclusiveTickData, Prefabs,
+    StateManager, SystemErrors, SystemFn, TickData, Time, events::Events, profiler::Profiler,
+    system_errors::catch_panic,
+};
+
+/// Systems run stage by stage, in this order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    PreUpdate,
+    /// Runs zero or more times per tick, with `dt` fixed to [`crate::Time::fixed_timestep`]
+    FixedUpdate,
+    Update,
+    PostUpdate,
+    RenderPrep,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [
+        Stage::PreUpdate,
+        Stage::FixedUpdate,
+        Stage::Update,
+        Stage::PostUpdate,
+        Stage::RenderPrep,
+    ];
+}
+
+/// Regular systems share the world and queue structural changes on a command buffer. Exclusive
+/// systems get the world to themselves, so they can spawn, despawn and insert immediately.
+#[derive(Clone, Copy)]
+pub enum System {
+    Regular(SystemFn),
+    Exclusive(ExclusiveSystemFn),
+}
+
+/// A system, along with where it runs. Systems with no constraints run in the order they were
+/// registered.
+///
+/// Systems that declare which components and states they use can run on the thread pool
+/// alongside their neighbours, as long as they don't conflict. Systems that declare nothing run
+/// on their own, as does every exclusive system.
+///
+/// ```ignore
+/// engine.add_system(
+///     SystemDescriptor::new("camera_follow", camera_follow)
+///         .in_stage(Stage::PostUpdate)
+///         .after("movement")
+///         .reads::<Transform>()
+///         .writes::<Camera>(),
+/// )?;
+/// ```
+#[derive(Clone)]
+pub struct SystemDescriptor {
+    pub(crate) name: String,
+    pub(crate) system: System,
+    pub(crate) stage: Stage,
+    before: Vec<String>,
+    after: Vec<String>,
+    access: Access,
+    error_policy: Option<ErrorPolicy>,
+    conditions: Vec<RunCondition>,
+    groups: Vec<String>,
+    run_mode: RunMode,
+}
+
+/// Decides whether a system runs this tick.
+pub type RunCondition = Arc<dyn Fn(&StateManager) -> bool + Send + Sync>;
+
+/// When a system runs, depending on whether the game is playing in the editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RunMode {
+    /// Only while playing; gameplay systems
+    #[default]
+    Play,
+    /// Only while the editor has the game stopped
+    Editor,
+    Always,
+}
+
+impl SystemDescriptor {
+    pub fn new(name: impl Into<String>, system: SystemFn) -> Self {
+        Self::with_system(name, System::Regular(system))
+    }
+
+    /// A system with mutable access to the world. Commands queued by earlier systems in the
+    /// stage are applied before it runs.
+    pub fn exclusive(name: impl Into<String>, system: ExclusiveSystemFn) -> Self {
+        Self::with_system(name, System::Exclusive(system))
+    }
+
+    fn with_system(name: impl Into<String>, system: System) -> Self {
+        Self {
+            name: name.into(),
+            system,
+            stage: Stage::Update,
+            before: Vec::new(),
+            after: Vec::new(),
+            access: Access::default(),
+            error_policy: None,
+            conditions: Vec::new(),
+            groups: Vec::new(),
+            run_mode: RunMode::default(),
+        }
+    }
+
+    pub fn in_stage(mut self, stage: Stage) -> Self {
+        self.stage = stage;
+        self
+    }
+
+    /// Run before `other`, if it's registered in the same stage.
+    pub fn before(mut self, other: impl Into<String>) -> Self {
+        self.before.push(other.into());
+        self
+    }
+
+    /// Run after `other`, if it's registered in the same stage.
+    pub fn after(mut self, other: impl Into<String>) -> Self {
+        self.after.push(other.into());
+        self
+    }
+
+    /// This system queries `C` immutably.
+    pub fn reads<C: hecs::Component>(mut self) -> Self {
+        self.access.reads.insert(TypeId::of::<C>());
+        self
+    }
+
+    /// This system queries `C` mutably.
+    pub fn writes<C: hecs::Component>(mut self) -> Self {
+        self.access.writes.insert(TypeId::of::<C>());
+        self
+    }
+
+    /// This system uses state `S`. States are handed out mutably, so two systems using the same
+    /// state never run at the same time. A system that declares its access can only get the
+    /// states it declared, whether or not it ends up running alongside others.
+    pub fn uses_state<S: 'static>(mut self) -> Self {
+        self.access.states.insert(TypeId::of::<S>());
+        self
+    }
+
+    /// What to do when this system fails, instead of the engine's policy.
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = Some(policy);
+        self
+    }
+
+    /// Only run when `condition` is true. With several conditions, they all have to be. See
+    /// [`crate::conditions`] for some common ones.
+    pub fn run_if(
+        mut self,
+        condition: impl Fn(&StateManager) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.conditions.push(Arc::new(condition));
+        self
+    }
+
+    /// Add this system to a group, so it can be switched on and off along with the rest of it.
+    pub fn in_group(mut self, group: impl Into<String>) -> Self {
+        self.groups.push(group.into());
+        self
+    }
+
+    pub fn run_mode(mut self, run_mode: RunMode) -> Self {
+        self.run_mode = run_mode;
+        self
+    }
+
+    fn can_run_in_parallel(&self) -> bool {
+        matches!(self.system, System::Regular(_)) && !self.access.is_empty()
+    }
+
+    fn conflicts_with(&self, other: &SystemDescriptor) -> bool {
+        self.access.conflicts_with(&other.access)
+            || self.before.contains(&other.name)
+            || self.after.contains(&other.name)
+            || other.before.contains(&self.name)
+            || other.after.contains(&self.name)
+    }
+}
+
+/// What a system has declared it touches.
+#[derive(Debug, Clone, Default)]
+struct Access {
+    reads: HashSet<TypeId>,
+    writes: HashSet<TypeId>,
+    states: HashSet<TypeId>,
+}
+
+impl Access {
+    fn is_empty(&self) -> bool {
+        self.reads.is_empty() && self.writes.is_empty() && self.states.is_empty()
+    }
+
+    fn conflicts_with(&self, other: &Access) -> bool {
+        !self.writes.is_disjoint(&other.writes)
+            || !self.writes.is_disjoint(&other.reads)
+            || !self.reads.is_disjoint(&other.writes)
+            || !self.states.is_disjoint(&other.states)
+    }
+}
+
+/// How many times to run [`Stage::FixedUpdate`] this tick, and with what `dt`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FixedSteps {
+    pub steps: u32,
+    pub timestep: f32,
+}
+
+#[derive(Default)]
+pub(crate) struct Schedule {
+    /// In registration order
+    systems: Vec<SystemDescriptor>,
+    /// Indices into `systems`, sorted and split into batches that can run in parallel, for
+    /// each stage
+    batches: BTreeMap<Stage, Vec<Vec<usize>>>,
+    /// Systems and groups switched off at runtime
+    disabled_systems: HashSet<String>,
+    disabled_groups: HashSet<String>,
+}
+
+impl Schedule {
+    /// Add a system, replacing any existing system with the same name. If it would create a
+    /// cycle the schedule is left untouched.
+    pub fn add(&mut self, descriptor: SystemDescriptor) -> Result<(), EngineError> {
+        let previous = self.systems.clone();
+
+        match self.systems.iter_mut().find(|s| s.name == descriptor.name) {
+            Some(existing) => *existing = descriptor,
+            None => self.systems.push(descriptor),
+        }
+
+        match sort(&self.systems) {
+            Ok(order) => {
+                self.batches = order
+                    .into_iter()
+                    .map(|(stage, order)| (stage, batch(&self.systems, &order)))
+                    .collect();
+                Ok(())
+            }
+            Err(e) => {
+                self.systems = previous;
+                Err(e)
+            }
+        }
+    }
+
+    /// Remove every system, but remember which systems and groups were switched off.
+    pub fn clear(&mut self) {
+        self.systems.clear();
+        self.batches.clear();
+    }
+
+    pub fn set_system_enabled(&mut self, name: &str, enabled: bool) {
+        set_enabled(&mut self.disabled_systems, name, enabled);
+    }
+
+    pub fn set_group_enabled(&mut self, group: &str, enabled: bool) {
+        set_enabled(&mut self.disabled_groups, group, enabled);
+    }
+
+    /// Whether a system has been switched off, by name or by group.
+    pub fn is_enabled(&self, system: &SystemDescriptor) -> bool {
+        !self.disabled_systems.contains(&system.name)
+            && !system
+                .groups
+                .iter()
+                .any(|group| self.disabled_groups.contains(group))
+    }
+
+    fn should_run(&self, system: &SystemDescriptor, context: &SystemContext) -> bool {
+        let mode_matches = match system.run_mode {
+            RunMode::Play => context.playing,
+            RunMode::Editor => !context.playing,
+            RunMode::Always => true,
+        };
+
+        mode_matches
+            && self.is_enabled(system)
+            && !context.errors.is_disabled(&system.name)
+            && system
+                .conditions
+                .iter()
+                .all(|condition| condition(context.state))
+    }
+
+    #[cfg(test)]
+    pub fn stage(&self, stage: Stage) -> impl Iterator<Item = &SystemDescriptor> {
+        self.batches
+            .get(&stage)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|index| &self.systems[*index])
+    }
+
+    /// Run every stage. Each stage's command buffer is applied before the next stage starts, so
+    /// anything spawned in one stage can be queried in the next. Failing systems are dealt with
+    /// according to their [`ErrorPolicy`].
+    pub fn run(&self, mut context: SystemContext, dt: f32, fixed: FixedSteps) {
+        let frame = context
+            .state
+            .get::<Time>()
+            .map_or(0, |time| time.frame_count);
+        context.errors.start_frame(frame);
+
+        for stage in Stage::ALL {
+            let (runs, dt) = match stage {
+                Stage::FixedUpdate => (fixed.steps, fixed.timestep),
+                _ => (1, dt),
+            };
+
+            for _ in 0..runs {
+                if self.run_stage(stage, &mut context, dt).is_break() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn run_stage(&self, stage: Stage, context: &mut SystemContext, dt: f32) -> ControlFlow<()> {
+        // Each system gets its own command buffer; they're applied in the order the systems ran
+        let mut command_buffers = Vec::new();
+        let flow = self.run_batches(stage, context, dt, &mut command_buffers);
+        apply(context.world, &mut command_buffers);
+        flow
+    }
+
+    fn run_batches(
+        &self,
+        stage: Stage,
+        context: &mut SystemContext,
+        dt: f32,
+        command_buffers: &mut Vec<CommandBuffer>,
+    ) -> ControlFlow<()> {
+        for batch in self.batches.get(&stage).into_iter().flatten() {
+            let systems: Vec<&SystemDescriptor> = batch
+                .iter()
+                .map(|index| &self.systems[*index])
+                .filter(|system| self.should_run(system, context))
+                .collect();
+
+            let shared = Shared {
+                world: context.world,
+                events: context.events,
+                profiler: context.profiler,
+                prefabs: context.prefabs,
+                component_registry: context.component_registry,
+            };
+
+            let [system] = systems[..] else {
+                let results = run_parallel(&systems, &shared, context.state, dt);
+                let mut flow = ControlFlow::Continue(());
+                for (system, (command_buffer, result)) in systems.iter().zip(results) {
+                    command_buffers.push(command_buffer);
+                    if context
+                        .errors
+                        .record(&system.name, system.error_policy, result)
+                        .is_break()
+                    {
+                        flow = ControlFlow::Break(());
+                    }
+                }
+                flow?;
+                continue;
+            };
+
+            let result = match system.system {
+                // Declaring access means getting only the declared states, even when alone
+                System::Regular(run) if system.can_run_in_parallel() => {
+                    let mut taken = context.state.take(&system.access.states);
+                    let (command_buffer, result) =
+                        run_regular(system, run, &shared, &mut taken, dt);
+                    context.state.restore(taken);
+                    command_buffers.push(command_buffer);
+                    result
+                }
+                System::Regular(run) => {
+                    let (command_buffer, result) =
+                        run_regular(system, run, &shared, context.state, dt);
+                    command_buffers.push(command_buffer);
+                    result
+                }
+                System::Exclusive(run) => {
+                    apply(context.world, command_buffers);
+                    log::trace!("[{}] system starting..", system.name);
+                    let _scope = context.profiler.scope("system", &system.name);
+                    let mut tick_data = ExclusiveTickData {
+                        dt,
+                        world: &mut *context.world,
+                        state: &mut *context.state,
+                        events: context.events,
+                        profiler: context.profiler,
+                        system: &system.name,
+                    };
+                    catch_panic(|| run(&mut tick_data))
+                }
+            };
+            context
+                .errors
+                .record(&system.name, system.error_policy, result)?;
+        }
+
+        ControlFlow::Continue(())
+    }
+}
+
+/// Everything systems run against.
+pub(crate) struct SystemContext<'a> {
+    pub world: &'a mut hecs::World,
+    pub state: &'a mut StateManager,
+    pub events: &'a Events,
+    pub profiler: &'a Profiler,
+    pub prefabs: &'a Prefabs,
+    pub component_registry: &'a ComponentRegistry,
+    pub errors: &'a mut SystemErrors,
+    /// Whether the game is playing, or stopped in the editor
+    pub playing: bool,
+}
+
+fn set_enabled(disabled: &mut HashSet<String>, name: &str, enabled: bool) {
+    if enabled {
+        disabled.remove(name);
+    } else {
+        disabled.insert(name.to_string());
+    }
+}
+
+/// What every system in a batch can share.
+struct Shared<'a> {
+    world: &'a hecs::World,
+    events: &'a Events,
+    profiler: &'a Profiler,
+    prefabs: &'a Prefabs,
+    component_registry: &'a ComponentRegistry,
+}
+
+fn run_regular(
+    system: &SystemDescriptor,
+    run: SystemFn,
+    shared: &Shared,
+    state: &mut StateManager,
+    dt: f32,
+) -> (CommandBuffer, anyhow::Result<()>) {
+    log::trace!("[{}] system starting..", system.name);
+    let _scope = shared.profiler.scope("system", &system.name);
+    let mut tick_data = TickData {
+        dt,
+        command_buffer: CommandBuffer::new(),
+        world: shared.world,
+        state,
+        events: shared.events,
+        profiler: shared.profiler,
+        prefabs: shared.prefabs,
+        component_registry: shared.component_registry,
+        system: &system.name,
+    };
+    let result = catch_panic(|| run(&mut tick_data));
+    (tick_data.command_buffer, result)
+}
+
+/// Run a batch on the thread pool, returning each system's commands and result in order. Each
+/// system gets only the states it declared, which are put back afterwards.
+fn run_parallel(
+    systems: &[&SystemDescriptor],
+    shared: &Shared,
+    state: &mut StateManager,
+    dt: f32,
+) -> Vec<(CommandBuffer, anyhow::Result<()>)> {
+    let mut states: Vec<StateManager> = systems
+        .iter()
+        .map(|system| state.take(&system.access.states))
+        .collect();
+
+    let results = systems
+        .par_iter()
+        .zip(states.par_iter_mut())
+        .map(|(system, state)| {
+            let System::Regular(run) = system.system else {
+                unreachable!("Exclusive systems are never batched");
+            };
+            run_regular(system, run, shared, state, dt)
+        })
+        .collect();
+
+    for taken in states {
+        state.restore(taken);
+    }
+
+    results
+}
+
+fn apply(world: &mut hecs::World, command_buffers: &mut Vec<CommandBuffer>) {
+    for mut command_buffer in command_buffers.drain(..) {
+        command_buffer.run_on(world);
+    }
+}
+
+/// Split a stage's sorted systems into runs of neighbours that don't conflict with each other.
+/// Only neighbours are grouped, so the sorted order is still respected.
+fn batch(systems: &[SystemDescriptor], order: &[usize]) -> Vec<Vec<usize>> {
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+
+    for &index in order {
+        let system = &systems[index];
+        match batches.last_mut() {
+            Some(batch)
+                if system.can_run_in_parallel()
+                    && batch.iter().all(|other| {
+                        let other = &systems[*other];
+                        other.can_run_in_parallel() && !system.conflicts_with(other)
+                    }) =>
+            {
+                batch.push(index)
+            }
+            _ => batches.push(vec![index]),
+        }
+    }
+
+    batches
+}
+
+/// Topologically sort each stage, breaking ties by registration order so the result is stable.
+fn sort(systems: &[SystemDescriptor]) -> Result<BTreeMap<Stage, Vec<usize>>, EngineError> {
+    let mut order = BTreeMap::new();
+
+    for stage in Stage::ALL {
+        let in_stage: HashMap<&str, usize> = systems
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.stage == stage)
+            .map(|(index, s)| (s.name.as_str(), index))
+            .collect();
+
+        // Edges go from a system to the systems that have to run after it
+        let mut dependents: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut dependencies: HashMap<usize, usize> =
+            in_stage.values().map(|index| (*index, 0)).collect();
+
+        for &index in in_stage.values() {
+            let system = &systems[index];
+            let edges = system
+                .before
+                .iter()
+                .filter_map(|other| in_stage.get(other.as_str()))
+                .map(|other| (index, *other))
+                .chain(
+                    system
+                        .after
+                        .iter()
+                        .filter_map(|other| in_stage.get(other.as_str()))
+                        .map(|other| (*other, index)),
+                );
+
+            for (from, to) in edges {
+                dependents.entry(from).or_default().push(to);
+                *dependencies.get_mut(&to).unwrap() += 1;
+            }
+        }
+
+        let mut ready: BTreeSet<usize> = dependencies
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(index, _)| *index)
+            .collect();
+        let mut sorted = Vec::with_capacity(in_stage.len());
+
+        while let Some(index) = ready.pop_first() {
+            sorted.push(index);
+            for dependent in dependents.get(&index).into_iter().flatten() {
+                let count = dependencies.get_mut(dependent).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.insert(*dependent);
+                }
+            }
+        }
+
+        if sorted.len() != in_stage.len() {
+            let mut cycle: Vec<String> = dependencies
+                .iter()
+                .filter(|(_, count)| **count > 0)
+                .map(|(index, _)| systems[*index].name.clone())
+                .collect();
+            cycle.sort();
+            return Err(EngineError::SystemCycle(cycle));
+        }
+
+        order.insert(stage, sorted);
+    }
+
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine_types::components::Transform;
+
+    fn noop(_: &mut TickData) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Run a single tick, with no fixed steps.
+    fn run(
+        schedule: &Schedule,
+        world: &mut hecs::World,
+        state: &mut StateManager,
+        errors: &mut SystemErrors,
+    ) {
+        let context = SystemContext {
+            world,
+            state,
+            events: &Events::default(),
+            profiler: &Profiler::default(),
+            prefabs: &Prefabs::default(),
+            component_registry: &ComponentRegistry::default(),
+            errors,
+            playing: true,
+        };
+        let fixed = FixedSteps {
+            steps: 0,
+            timestep: 0.,
+        };
+        schedule.run(context, 0., fixed);
+    }
+
+    fn batches(schedule: &Schedule, stage: Stage) -> Vec<Vec<&str>> {
+        schedule.batches[&stage]
+            .iter()
+            .map(|batch| {
+                batch
+                    .iter()
+                    .map(|index| schedule.systems[*index].name.as_str())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn names(schedule: &Schedule) -> Vec<&str> {
+        Stage::ALL
+            .into_iter()
+            .flat_map(|stage| schedule.stage(stage))
+            .map(|system| system.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut schedule = Schedule::default();
+        schedule
+            .add(SystemDescriptor::new("camera_follow", noop).after("movement"))
+            .unwrap();
+        schedule
+            .add(SystemDescriptor::new("movement", noop).after("input"))
+            .unwrap();
+        schedule
+            .add(SystemDescriptor::new("input", noop).in_stage(Stage::PreUpdate))
+            .unwrap();
+        schedule
+            .add(SystemDescriptor::new("physics", noop).before("movement"))
+            .unwrap();
+
+        assert_eq!(
+            names(&schedule),
+            ["input", "physics", "movement", "camera_follow"]
+        );
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut schedule = Schedule::default();
+        schedule
+            .add(SystemDescriptor::new("a", noop).after("b"))
+            .unwrap();
+
+        let Err(EngineError::SystemCycle(cycle)) =
+            schedule.add(SystemDescriptor::new("b", noop).after("a"))
+        else {
+            panic!("Expected a cycle");
+        };
+        assert_eq!(cycle, ["a", "b"]);
+
+        // The schedule is left as it was
+        assert_eq!(names(&schedule), ["a"]);
+    }
+
+    fn spawn_projectile(tick: &mut TickData) -> anyhow::Result<()> {
+        tick.command_buffer.spawn((1_u32,));
+        Ok(())
+    }
+
+    fn count_projectiles(tick: &mut ExclusiveTickData) -> anyhow::Result<()> {
+        let count = tick.world.query_mut::<&u32>().into_iter().count();
+        tick.state.insert_state(count);
+        Ok(())
+    }
+
+    fn despawn_projectiles(tick: &mut ExclusiveTickData) -> anyhow::Result<()> {
+        tick.world.clear();
+        Ok(())
+    }
+
+    #[test]
+    fn test_structural_changes() {
+        let mut schedule = Schedule::default();
+        schedule
+            .add(SystemDescriptor::new("spawn", spawn_projectile).in_stage(Stage::PreUpdate))
+            .unwrap();
+        schedule
+            .add(SystemDescriptor::exclusive("count", count_projectiles))
+            .unwrap();
+        schedule
+            .add(
+                SystemDescriptor::exclusive("despawn", despawn_projectiles)
+                    .in_stage(Stage::PostUpdate),
+            )
+            .unwrap();
+
+        let mut world = hecs::World::new();
+        let mut state = StateManager::default();
+        run(
+            &schedule,
+            &mut world,
+            &mut state,
+            &mut SystemErrors::default(),
+        );
+
+        // The projectile was spawned in time for the next stage to see it..
+        assert_eq!(*state.get_state::<usize>().unwrap(), 1);
+        // ..and despawned immediately
+        assert!(world.is_empty());
+    }
+
+    #[test]
+    fn test_batching() {
+        type Velocity = glam::Vec3;
+        type Health = f32;
+        type Score = usize;
+
+        let mut schedule = Schedule::default();
+        for descriptor in [
+            SystemDescriptor::new("movement", noop)
+                .writes::<Transform>()
+                .reads::<Velocity>(),
+            SystemDescriptor::new("regen", noop).writes::<Health>(),
+            SystemDescriptor::new("scoring", noop)
+                .reads::<Health>()
+                .uses_state::<Score>(),
+            SystemDescriptor::new("combo", noop).uses_state::<Score>(),
+            SystemDescriptor::new("undeclared", noop),
+            SystemDescriptor::new("camera", noop).reads::<Transform>(),
+            SystemDescriptor::new("ui", noop)
+                .reads::<Health>()
+                .after("camera"),
+        ] {
+            schedule.add(descriptor).unwrap();
+        }
+
+        assert_eq!(
+            batches(&schedule, Stage::Update),
+            [
+                vec!["movement", "regen"],
+                vec!["scoring"],
+                vec!["combo"],
+                vec!["undeclared"],
+                vec!["camera"],
+                vec!["ui"],
+            ]
+        );
+    }
+
+    fn add_score(tick: &mut TickData) -> anyhow::Result<()> {
+        *tick.get_state::<usize>()? += 1;
+        Ok(())
+    }
+
+    fn spawn_marker(tick: &mut TickData) -> anyhow::Result<()> {
+        tick.command_buffer.spawn((1_u32,));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_run() {
+        let mut schedule = Schedule::default();
+        schedule
+            .add(SystemDescriptor::new("score", add_score).uses_state::<usize>())
+            .unwrap();
+        schedule
+            .add(SystemDescriptor::new("spawn", spawn_marker).writes::<u32>())
+            .unwrap();
+        assert_eq!(batches(&schedule, Stage::Update), [["score", "spawn"]]);
+
+        let mut world = hecs::World::new();
+        let mut state = StateManager::default();
+        state.insert_state(0_usize);
+        run(
+            &schedule,
+            &mut world,
+            &mut state,
+            &mut SystemErrors::default(),
+        );
+
+        // The state was handed back, and the command buffer applied
+        assert_eq!(*state.get_state::<usize>().unwrap(), 1);
+        assert_eq!(world.len(), 1);
+    }
+
+    fn read_score(tick: &mut TickData) -> anyhow::Result<()> {
+        tick.get_state::<usize>()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_undeclared_state() {
+        let mut schedule = Schedule::default();
+        schedule
+            .add(SystemDescriptor::new("sneaky", read_score).reads::<u32>())
+            .unwrap();
+        assert_eq!(batches(&schedule, Stage::Update), [["sneaky"]]);
+
+        let mut world = hecs::World::new();
+        let mut state = StateManager::default();
+        state.insert_state(0_usize);
+        let mut errors = SystemErrors::default();
+        run(&schedule, &mut world, &mut state, &mut errors);
+
+        // Running alone doesn't get it states it didn't declare, and they're all put back
+        assert_eq!(errors.recent().len(), 1);
+        assert!(state.contains_state::<usize>());
+    }
+
+    fn fail(_: &mut TickData) -> anyhow::Result<()> {
+        anyhow::bail!("Oh no")
+    }
+
+    #[test]
+    fn test_error_policy() {
+        let mut schedule = Schedule::default();
+        schedule
+            .add(SystemDescriptor::new("fail", fail).on_error(ErrorPolicy::DisableAfter(2)))
+            .unwrap();
+        schedule
+            .add(SystemDescriptor::new("score", add_score))
+            .unwrap();
+
+        let mut world = hecs::World::new();
+        let mut state = StateManager::default();
+        state.insert_state(0_usize);
+        let mut errors = SystemErrors::default();
+        for _ in 0..3 {
+            run(&schedule, &mut world, &mut state, &mut errors);
+        }
+
+        // The failing system didn't stop the rest, and was disabled after its second failure
+        assert_eq!(*state.get_state::<usize>().unwrap(), 3);
+        assert!(errors.is_disabled("fail"));
+        let disabled: Vec<bool> = errors.recent().iter().map(|e| e.disabled).collect();
+        assert_eq!(disabled, [false, true]);
+        assert_eq!(errors.recent()[0].message, "Oh no");
+
+        // Re-registering gives it another go
+        schedule
+            .add(SystemDescriptor::new("fail", fail).on_error(ErrorPolicy::SkipRest))
+            .unwrap();
+        errors.forget("fail");
+        run(&schedule, &mut world, &mut state, &mut errors);
+        assert_eq!(*state.get_state::<usize>().unwrap(), 3);
+        assert_eq!(errors.recent().len(), 3);
+    }
+
+    fn explode(_: &mut TickData) -> anyhow::Result<()> {
+        panic!("Kaboom")
+    }
+
+    #[test]
+    fn test_panic() {
+        let mut schedule = Schedule::default();
+        schedule
+            .add(SystemDescriptor::new("explode", explode))
+            .unwrap();
+        schedule
+            .add(SystemDescriptor::new("score", add_score))
+            .unwrap();
+
+        let mut world = hecs::World::new();
+        let mut state = StateManager::default();
+        state.insert_state(0_usize);
+        let mut errors = SystemErrors::default();
+        run(&schedule, &mut world, &mut state, &mut errors);
+        run(&schedule, &mut world, &mut state, &mut errors);
+
+        // The panic was caught, and the system faulted until it's cleared
+        assert_eq!(*state.get_state::<usize>().unwrap(), 2);
+        assert_eq!(errors.recent().len(), 1);
+        assert!(errors.recent()[0].panicked);
+        assert_eq!(errors.recent()[0].message, "Panicked: Kaboom");
+
+        errors.clear_faults();
+        run(&schedule, &mut world, &mut state, &mut errors);
+        assert_eq!(errors.recent().len(), 2);
+    }
+
+    #[test]
+    fn test_run_criteria() {
+        let mut schedule = Schedule::default();
+        schedule
+            .add(
+                SystemDescriptor::new("score", add_score)
+                    .run_if(crate::conditions::state_exists::<u32>())
+                    .in_group("scoring"),
+            )
+            .unwrap();
+
+        let mut world = hecs::World::new();
+        let mut state = StateManager::default();
+        let mut errors = SystemErrors::default();
+        state.insert_state(0_usize);
+
+        let mut score = |schedule: &Schedule, state: &mut StateManager| {
+            run(schedule, &mut world, state, &mut errors);
+            *state.get_state::<usize>().unwrap()
+        };
+
+        assert_eq!(score(&schedule, &mut state), 0);
+        state.insert_state(0_u32);
+        assert_eq!(score(&schedule, &mut state), 1);
+
+        schedule.set_group_enabled("scoring", false);
+        assert_eq!(score(&schedule, &mut state), 1);
+        schedule.set_group_enabled("scoring", true);
+        schedule.set_system_enabled("score", false);
+        assert_eq!(score(&schedule, &mut state), 1);
+        schedule.set_system_enabled("score", true);
+        assert_eq!(score(&schedule, &mut state), 2);
+
+        // Gameplay systems don't run while the editor has the game stopped
+        let context = SystemContext {
+            world: &mut hecs::World::new(),
+            state: &mut state,
+            events: &Events::default(),
+            profiler: &Profiler::default(),
+            prefabs: &Prefabs::default(),
+            component_registry: &ComponentRegistry::default(),
+            errors: &mut SystemErrors::default(),
+            playing: false,
+        };
+        let fixed = FixedSteps {
+            steps: 0,
+            timestep: 0.,
+        };
+        schedule.run(context, 0., fixed);
+        assert_eq!(*state.get_state::<usize>().unwrap(), 2);
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    any::{self, Any, TypeId},
+    collections::{HashMap, HashSet},
+};
+
+use crate::EngineError;
+
+type StateMap = HashMap<TypeId, Box<dyn Any + Send>>;
+
+/// Global state shared between systems, one of each type.
+#[derive(Debug, Default)]
+pub struct StateManager {
+    inner: StateMap,
+}
+
+impl StateManager {
+    pub fn get_state<S: 'static>(&mut self) -> Result<&mut S, EngineError> {
+        // heheeheh
+        self.inner
+            .get_mut(&TypeId::of::<S>())
+            .map(|s| s.downcast_mut())
+            .flatten()
+            .ok_or_else(|| EngineError::UninitialisedState(any::type_name::<S>()))
+    }
+
+    /// Borrow several different states mutably at once, eg. `get_states::<(Score, Level)>()`.
+    /// Asking for the same state twice is an error.
+    pub fn get_states<'a, S: States<'a>>(&'a mut self) -> Result<S::Refs, EngineError> {
+        S::get(self)
+    }
+
+    pub fn insert_state<S: Send + 'static>(&mut self, state: S) {
+        let type_id = TypeId::of::<S>();
+        self.inner.insert(type_id, Box::new(state));
+    }
+
+    pub fn get_or_insert_with<S: Send + 'static>(&mut self, f: impl FnOnce() -> S) -> &mut S {
+        self.inner
+            .entry(TypeId::of::<S>())
+            .or_insert_with(|| Box::new(f()))
+            .downcast_mut()
+            .unwrap()
+    }
+
+    pub fn contains_state<S: 'static>(&self) -> bool {
+        self.inner.contains_key(&TypeId::of::<S>())
+    }
+
+    pub fn remove_state<S: 'static>(&mut self) -> Option<S> {
+        let state = self.inner.remove(&TypeId::of::<S>())?;
+        state.downcast().ok().map(|state| *state)
+    }
+
+    /// Move the given states out into their own manager, so they can be handed to another thread.
+    pub(crate) fn take(&mut self, type_ids: &HashSet<TypeId>) -> StateManager {
+        let inner = type_ids
+            .iter()
+            .filter_map(|type_id| Some((*type_id, self.inner.remove(type_id)?)))
+            .collect();
+        StateManager { inner }
+    }
+
+    /// Put back states moved out with [`StateManager::take`].
+    pub(crate) fn restore(&mut self, taken: StateManager) {
+        self.inner.extend(taken.inner);
+    }
+
+    /// Shared access, for the renderer and run conditions.
+    pub fn get<S: 'static>(&self) -> Option<&S> {
+        self.inner
+            .get(&TypeId::of::<S>())
+            .and_then(|s| s.downcast_ref())
+    }
+}
+
+/// A tuple of distinct state types that can be borrowed together with
+/// [`StateManager::get_states`].
+pub trait States<'a> {
+    type Refs;
+
+    fn get(manager: &'a mut StateManager) -> Result<Self::Refs, EngineError>;
+}
+
+macro_rules! impl_states {
+    ($($state:ident $value:ident),+) => {
+        impl<'a, $($state: 'static),+> States<'a> for ($($state,)+) {
+            type Refs = ($(&'a mut $state,)+);
+
+            fn get(manager: &'a mut StateManager) -> Result<Self::Refs, EngineError> {
+                let type_ids = [$(TypeId::of::<$state>()),+];
+                let names = [$(any::type_name::<$state>()),+];
+                for (index, type_id) in type_ids.iter().enumerate() {
+                    // get_disjoint_mut panics on duplicates, so catch them first
+                    if type_ids[..index].contains(type_id) {
+                        return Err(EngineError::DuplicateState(names[index]));
+                    }
+                }
+
+                let [$($value),+] = manager.inner.get_disjoint_mut(type_ids.each_ref());
+                Ok(($(
+                    $value
+                        .and_then(|s| s.downcast_mut::<$state>())
+                        .ok_or_else(|| EngineError::UninitialisedState(any::type_name::<$state>()))?,
+                )+))
+            }
+        }
+    };
+}
+
+impl_states!(A a, B b);
+impl_states!(A a, B b, C c);
+impl_states!(A a, B b, C c, D d);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Score(u32);
+    #[derive(Debug, PartialEq)]
+    struct Level(u32);
+
+    #[test]
+    fn test_states() {
+        let mut state = StateManager::default();
+        state.insert_state(Score(0));
+        assert!(state.contains_state::<Score>());
+        assert!(!state.contains_state::<Level>());
+
+        state.get_or_insert_with(|| Level(1)).0 += 1;
+        state.get_or_insert_with(|| Level(1)).0 += 1;
+
+        let (score, level) = state.get_states::<(Score, Level)>().unwrap();
+        score.0 += level.0;
+        assert_eq!(state.get_state::<Score>().unwrap(), &Score(3));
+
+        assert!(matches!(
+            state.get_states::<(Score, Score)>(),
+            Err(EngineError::DuplicateState(_))
+        ));
+        assert!(matches!(
+            state.get_states::<(Score, Level, u32)>(),
+            Err(EngineError::UninitialisedState(_))
+        ));
+
+        assert_eq!(state.remove_state::<Level>(), Some(Level(3)));
+        assert!(!state.contains_state::<Level>());
+        assert_eq!(state.remove_state::<Level>(), None);
+    }
+}
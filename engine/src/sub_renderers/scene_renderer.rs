@@ -1,5 +1,8 @@
 use crate::{TickData, TickDataFamily};
-use engine_types::components::{GLTFAsset, Transform};
+use engine_types::{
+    SceneSettings,
+    components::{GLTFAsset, Transform},
+};
 use glam::Quat;
 use lazy_vulkan::{BufferAllocation, ImageManager, LazyVulkan, Pipeline, SubRenderer, ash::vk};
 use lazy_vulkan_gltf::LoadedAsset;
@@ -44,6 +47,9 @@ impl<'a> SubRenderer<'a> for SceneRenderer {
         allocator: &mut lazy_vulkan::Allocator,
         image_manager: &mut ImageManager,
     ) {
+        let _scope = state
+            .profiler
+            .scope("renderer", "Mesh Renderer: stage_transfers");
         for (_, asset) in state.world.query::<&GLTFAsset>().iter() {
             let key = &asset.path;
             if self.assets.contains_key(key) {
@@ -71,13 +77,49 @@ impl<'a> SubRenderer<'a> for SceneRenderer {
         context: &lazy_vulkan::Context,
         params: lazy_vulkan::DrawParams,
     ) {
+        let _scope = state
+            .profiler
+            .scope("renderer", "Mesh Renderer: draw_opaque");
         self.begin_rendering(context, &self.pipeline);
 
         let device = &context.device;
         let command_buffer = context.draw_command_buffer;
         let world = &state.world;
+        let settings = state.state.get::<SceneSettings>();
+
+        // Paint the scene's background over whatever was there
+        let clear_colour = settings
+            .map(|settings| settings.clear_colour)
+            .unwrap_or_else(|| SceneSettings::default().clear_colour);
+        unsafe {
+            device.cmd_clear_attachments(
+                command_buffer,
+                &[vk::ClearAttachment {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    color_attachment: 0,
+                    clear_value: vk::ClearValue {
+                        color: vk::ClearColorValue {
+                            float32: clear_colour.to_array(),
+                        },
+                    },
+                }],
+                &[vk::ClearRect {
+                    rect: vk::Rect2D {
+                        offset: vk::Offset2D::default(),
+                        extent: params.drawable.extent,
+                    },
+                    base_array_layer: 0,
+                    layer_count: 1,
+                }],
+            );
+        }
 
-        let mvp = build_mvp(params.drawable.extent);
+        let camera = settings
+            .and_then(|settings| settings.active_camera)
+            .and_then(|camera| camera.entity())
+            .and_then(|camera| world.get::<&Transform>(camera).ok())
+            .map(|transform| Transform::clone(&transform));
+        let mvp = build_mvp(params.drawable.extent, camera.as_ref());
 
         for (_, (asset, transform)) in world.query::<(&GLTFAsset, &Transform)>().iter() {
             let Some(asset) = self.assets.get(&asset.path) else {
@@ -124,7 +166,7 @@ struct Registers {
 unsafe impl bytemuck::Zeroable for Registers {}
 unsafe impl bytemuck::Pod for Registers {}
 
-fn build_mvp(extent: vk::Extent2D) -> glam::Mat4 {
+fn build_mvp(extent: vk::Extent2D, camera: Option<&Transform>) -> glam::Mat4 {
     // Build up the perspective matrix
     let aspect_ratio = extent.width as f32 / extent.height as f32;
     let mut perspective =
@@ -133,12 +175,14 @@ fn build_mvp(extent: vk::Extent2D) -> glam::Mat4 {
     // WULKAN
     perspective.y_axis *= -1.0;
 
-    // Get view_from_world
-    // TODO: camera
-    let world_from_view = glam::Affine3A::from_rotation_translation(
-        Quat::from_euler(glam::EulerRot::YXZ, TAU * 0.1, -TAU * 0.1, 0.),
-        glam::Vec3::new(8., 4., 4.),
-    );
+    // Get view_from_world, from the scene's camera if it has one
+    let world_from_view = match camera {
+        Some(camera) => glam::Affine3A::from_rotation_translation(camera.rotation, camera.position),
+        None => glam::Affine3A::from_rotation_translation(
+            Quat::from_euler(glam::EulerRot::YXZ, TAU * 0.1, -TAU * 0.1, 0.),
+            glam::Vec3::new(8., 4., 4.),
+        ),
+    };
     let view_from_world = world_from_view.inverse();
 
     perspective * view_from_world
@@ -0,0 +1,147 @@
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    fmt,
+    ops::ControlFlow,
+    panic::{self, AssertUnwindSafe},
+};
+
+use engine_types::SystemError;
+
+/// What to do when a system returns an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Log it and carry on with the next system
+    #[default]
+    Continue,
+    /// Skip every system left to run this tick. Commands already queued are still applied.
+    SkipRest,
+    /// Carry on, but stop running the system once it's failed this many times
+    DisableAfter(u32),
+}
+
+/// A system panicked. Panicking systems are faulted until the next hot reload, whatever their
+/// policy.
+#[derive(Debug)]
+pub(crate) struct SystemPanic(String);
+
+impl fmt::Display for SystemPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Panicked: {}", self.0)
+    }
+}
+
+impl std::error::Error for SystemPanic {}
+
+/// Run a system, turning a panic into a [`SystemPanic`] error rather than letting it unwind
+/// into the engine (and take the editor down with it).
+pub(crate) fn catch_panic(run: impl FnOnce() -> anyhow::Result<()>) -> anyhow::Result<()> {
+    // The system's state may be left half-updated, but that beats losing the whole editor
+    panic::catch_unwind(AssertUnwindSafe(run))
+        .unwrap_or_else(|payload| Err(SystemPanic(panic_message(payload)).into()))
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "<unknown panic>".into()
+    }
+}
+
+/// How many of the most recent errors to keep around for the editor.
+const MAX_RECENT: usize = 64;
+
+/// Keeps track of failing systems and applies their [`ErrorPolicy`].
+#[derive(Debug, Default)]
+pub(crate) struct SystemErrors {
+    /// Used for systems that don't have a policy of their own
+    pub policy: ErrorPolicy,
+    failures: HashMap<String, u32>,
+    disabled: HashSet<String>,
+    /// Systems that panicked, which won't run again until the next hot reload
+    faulted: HashSet<String>,
+    recent: Vec<SystemError>,
+    frame: u64,
+}
+
+impl SystemErrors {
+    /// Errors recorded from now on happened on this frame.
+    pub fn start_frame(&mut self, frame: u64) {
+        self.frame = frame;
+    }
+
+    pub fn is_disabled(&self, system: &str) -> bool {
+        self.disabled.contains(system) || self.faulted.contains(system)
+    }
+
+    /// Log a system's result, returning whether the rest of the tick should run.
+    pub fn record(
+        &mut self,
+        system: &str,
+        policy: Option<ErrorPolicy>,
+        result: anyhow::Result<()>,
+    ) -> ControlFlow<()> {
+        let error = match result {
+            Ok(()) => {
+                log::trace!("[{system}] system complete");
+                return ControlFlow::Continue(());
+            }
+            Err(error) => error,
+        };
+        log::error!("[{system}]: {error:?}");
+
+        let failures = self.failures.entry(system.to_string()).or_default();
+        *failures += 1;
+
+        let policy = policy.unwrap_or(self.policy);
+        let panicked = error.is::<SystemPanic>();
+        let disabled = matches!(policy, ErrorPolicy::DisableAfter(limit) if *failures >= limit);
+        if panicked {
+            log::warn!("[{system}] panicked, it won't run again until the next hot reload");
+            self.faulted.insert(system.to_string());
+        } else if disabled {
+            log::warn!("[{system}] has failed {failures} times, disabling it");
+            self.disabled.insert(system.to_string());
+        }
+
+        if self.recent.len() == MAX_RECENT {
+            self.recent.remove(0);
+        }
+        self.recent.push(SystemError {
+            system: system.to_string(),
+            message: format!("{error:#}"),
+            frame: self.frame,
+            disabled: disabled || panicked,
+            panicked,
+        });
+
+        match policy {
+            ErrorPolicy::SkipRest => ControlFlow::Break(()),
+            _ => ControlFlow::Continue(()),
+        }
+    }
+
+    /// Give a system a clean slate, re-enabling it if it was disabled.
+    pub fn forget(&mut self, system: &str) {
+        self.failures.remove(system);
+        self.disabled.remove(system);
+        self.faulted.remove(system);
+    }
+
+    /// Let systems that panicked run again.
+    pub fn clear_faults(&mut self) {
+        self.faulted.clear();
+    }
+
+    /// The most recent errors, oldest first.
+    pub fn recent(&self) -> &[SystemError] {
+        &self.recent
+    }
+
+    pub fn clear(&mut self) {
+        self.recent.clear();
+    }
+}
@@ -0,0 +1,121 @@
+use std::time::Instant;
+
+/// The engine's clock. Systems can read (or pause and scale) it with `tick.get_state::<Time>()`.
+#[derive(Debug, Clone)]
+pub struct Time {
+    /// Scaled seconds since the last tick; zero while paused
+    pub delta: f32,
+    /// Seconds since the last tick, ignoring scale and pause
+    pub real_delta: f32,
+    /// Scaled seconds the game has been running for
+    pub elapsed: f64,
+    /// Ticks since the engine started. This counts real frames, so it keeps going while
+    /// paused or stopped
+    pub frame_count: u64,
+    pub time_scale: f32,
+    pub paused: bool,
+    /// Seconds per [`crate::Stage::FixedUpdate`] step
+    pub fixed_timestep: f32,
+    /// The most fixed steps a single tick will run, so one slow frame can't snowball
+    pub max_fixed_steps: u32,
+    accumulator: f32,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            delta: 0.,
+            real_delta: 0.,
+            elapsed: 0.,
+            frame_count: 0,
+            time_scale: 1.,
+            paused: false,
+            fixed_timestep: 1. / 60.,
+            max_fixed_steps: 8,
+            accumulator: 0.,
+        }
+    }
+}
+
+impl Time {
+    /// How far we are between the last fixed step and the next, from 0 to 1. Handy for
+    /// interpolating things that move in fixed steps.
+    pub fn fixed_alpha(&self) -> f32 {
+        self.accumulator / self.fixed_timestep
+    }
+
+    /// Advance the clock by `real_delta` seconds, returning how many fixed steps to run.
+    pub(crate) fn advance(&mut self, real_delta: f32, paused: bool) -> u32 {
+        self.real_delta = real_delta;
+        self.delta = if paused || self.paused {
+            0.
+        } else {
+            real_delta * self.time_scale
+        };
+        self.elapsed += self.delta as f64;
+        self.frame_count += 1;
+
+        self.accumulator += self.delta;
+        let mut steps = 0;
+        while self.fixed_timestep > 0. && self.accumulator >= self.fixed_timestep {
+            if steps == self.max_fixed_steps {
+                // We're too far behind to ever catch up, so drop the backlog
+                self.accumulator %= self.fixed_timestep;
+                break;
+            }
+
+            self.accumulator -= self.fixed_timestep;
+            steps += 1;
+        }
+
+        steps
+    }
+}
+
+/// Measures real time between ticks.
+#[derive(Debug, Default)]
+pub(crate) struct Clock {
+    last_tick: Option<Instant>,
+}
+
+impl Clock {
+    /// Seconds since this was last called; zero the first time.
+    pub fn tick(&mut self) -> f32 {
+        let now = Instant::now();
+        let delta = self
+            .last_tick
+            .map_or(0., |last_tick| (now - last_tick).as_secs_f32());
+        self.last_tick = Some(now);
+        delta
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_steps() {
+        let mut time = Time {
+            fixed_timestep: 0.1,
+            ..Default::default()
+        };
+
+        assert_eq!(time.advance(0.05, false), 0);
+        assert_eq!(time.advance(0.1, false), 1);
+        assert!((time.fixed_alpha() - 0.5).abs() < 1e-4);
+
+        // Scaled time feeds the accumulator, paused time doesn't (but still counts a frame)
+        time.time_scale = 2.;
+        assert_eq!(time.advance(0.1, false), 2);
+        assert_eq!(time.advance(0.1, true), 0);
+        assert_eq!(time.delta, 0.);
+        assert!((time.fixed_alpha() - 0.5).abs() < 1e-4);
+        assert_eq!(time.frame_count, 4);
+
+        // A huge hitch is clamped, and the backlog dropped
+        time.time_scale = 1.;
+        assert_eq!(time.advance(10., false), time.max_fixed_steps);
+        assert!(time.fixed_alpha() < 1.);
+    }
+}
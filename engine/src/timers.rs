@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use hecs::CommandBuffer;
+use serde::{Deserialize, Serialize};
+
+/// Countdowns for gameplay code, advanced by the engine with [`crate::Time::delta`] so they
+/// pause and scale with the game. Systems get them with `tick.get_state::<Timers>()`.
+///
+/// Timer names are shared by every system.
+#[derive(Default)]
+pub struct Timers {
+    timers: HashMap<String, Timer>,
+    delayed: Vec<(f32, CommandBuffer)>,
+}
+
+#[derive(Debug, Clone)]
+struct Timer {
+    remaining: f32,
+    /// `Some` for timers that start again when they finish
+    period: Option<f32>,
+    /// How many times it finished this tick
+    finished: u32,
+}
+
+impl Timers {
+    /// Start a timer that finishes once, `seconds` from now, replacing any timer with that name.
+    pub fn start(&mut self, name: impl Into<String>, seconds: f32) {
+        self.insert(name.into(), seconds, None);
+    }
+
+    /// Start a timer that finishes every `seconds`, until it's cancelled.
+    pub fn start_repeating(&mut self, name: impl Into<String>, seconds: f32) {
+        self.insert(name.into(), seconds, Some(seconds));
+    }
+
+    fn insert(&mut self, name: String, seconds: f32, period: Option<f32>) {
+        let timer = Timer {
+            remaining: seconds,
+            period,
+            finished: 0,
+        };
+        self.timers.insert(name, timer);
+    }
+
+    pub fn cancel(&mut self, name: &str) {
+        self.timers.remove(name);
+    }
+
+    /// Whether the timer finished this tick.
+    pub fn just_finished(&self, name: &str) -> bool {
+        self.times_finished(name) > 0
+    }
+
+    /// How many times the timer finished this tick. Repeating timers that are shorter than a
+    /// tick can finish more than once.
+    pub fn times_finished(&self, name: &str) -> u32 {
+        self.timers.get(name).map_or(0, |timer| timer.finished)
+    }
+
+    /// Whether there's a timer with this name that hasn't finished yet.
+    pub fn is_running(&self, name: &str) -> bool {
+        self.remaining(name).is_some()
+    }
+
+    /// Seconds until the timer next finishes.
+    pub fn remaining(&self, name: &str) -> Option<f32> {
+        self.timers
+            .get(name)
+            .filter(|timer| timer.remaining > 0.)
+            .map(|timer| timer.remaining)
+    }
+
+    /// Run `commands` on the world `seconds` from now, before that tick's systems.
+    pub fn after(&mut self, seconds: f32, commands: CommandBuffer) {
+        self.delayed.push((seconds, commands));
+    }
+
+    /// Advance every timer by `dt`, returning the delayed commands that are due.
+    pub(crate) fn advance(&mut self, dt: f32) -> Vec<CommandBuffer> {
+        self.timers.retain(|_, timer| {
+            // Finished one-shot timers are kept for a tick, so systems can see they finished
+            if timer.finished > 0 && timer.period.is_none() {
+                return false;
+            }
+
+            timer.finished = 0;
+            timer.remaining -= dt;
+            while timer.remaining <= 0. {
+                timer.finished += 1;
+                match timer.period {
+                    Some(period) if period > 0. => timer.remaining += period,
+                    _ => break,
+                }
+            }
+            true
+        });
+
+        let mut due = Vec::new();
+        let mut waiting = Vec::new();
+        for (remaining, commands) in self.delayed.drain(..) {
+            let remaining = remaining - dt;
+            if remaining <= 0. {
+                due.push(commands);
+            } else {
+                waiting.push((remaining, commands));
+            }
+        }
+        self.delayed = waiting;
+        due
+    }
+
+    /// A copy of the timers, leaving out the delayed commands, which can't be copied.
+    pub(crate) fn without_delayed(&self) -> Timers {
+        Timers {
+            timers: self.timers.clone(),
+            delayed: Vec::new(),
+        }
+    }
+
+    /// Drop delayed commands, whose components might belong to code that's about to be
+    /// unloaded. The timers themselves are just numbers, so they're kept.
+    pub(crate) fn drop_delayed(&mut self) {
+        if !self.delayed.is_empty() {
+            log::warn!("Dropping {} delayed commands", self.delayed.len());
+        }
+        self.delayed.clear();
+    }
+}
+
+/// A per-entity countdown, eg. between attacks or for invulnerability frames. The engine counts
+/// it down every tick, with [`crate::Time::delta`]. It's registered with the engine's component
+/// registry, so it's saved and snapshotted like any other component.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Cooldown {
+    pub remaining: f32,
+}
+
+impl engine_types::CanYak for Cooldown {
+    fn get_paint_fn() -> engine_types::PaintFn {
+        // The engine doesn't draw; the editor shows it from its JSON
+        Box::new(|_, _| {})
+    }
+}
+
+impl Cooldown {
+    /// A cooldown that's already running.
+    pub fn new(seconds: f32) -> Self {
+        Self { remaining: seconds }
+    }
+
+    pub fn start(&mut self, seconds: f32) {
+        self.remaining = seconds;
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.remaining <= 0.
+    }
+
+    /// Start the cooldown if it's ready, returning whether it was. Handy for
+    /// `if cooldown.trigger(0.5) { attack() }`.
+    pub fn trigger(&mut self, seconds: f32) -> bool {
+        let ready = self.is_ready();
+        if ready {
+            self.start(seconds);
+        }
+        ready
+    }
+}
+
+/// Count every [`Cooldown`] in the world down by `dt`.
+pub(crate) fn advance_cooldowns(world: &mut hecs::World, dt: f32) {
+    for (_, cooldown) in world.query_mut::<&mut Cooldown>() {
+        cooldown.remaining = (cooldown.remaining - dt).max(0.);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timers() {
+        let mut timers = Timers::default();
+        timers.start("coyote_time", 0.2);
+        timers.start_repeating("spawner", 0.5);
+
+        timers.advance(0.125);
+        assert!(timers.is_running("coyote_time"));
+        assert!(!timers.just_finished("spawner"));
+
+        timers.advance(0.125);
+        assert!(timers.just_finished("coyote_time"));
+        assert!(!timers.is_running("coyote_time"));
+
+        // Finished one-shot timers are gone the tick after
+        timers.advance(0.125);
+        assert!(!timers.just_finished("coyote_time"));
+
+        timers.advance(1.0);
+        assert_eq!(timers.times_finished("spawner"), 2);
+        assert_eq!(timers.remaining("spawner"), Some(0.125));
+
+        timers.cancel("spawner");
+        timers.advance(1.0);
+        assert!(!timers.just_finished("spawner"));
+    }
+
+    #[test]
+    fn test_delayed_commands() {
+        let mut timers = Timers::default();
+        let mut world = hecs::World::new();
+        let mut commands = CommandBuffer::new();
+        commands.spawn((Cooldown::new(1.0),));
+        timers.after(0.5, commands);
+
+        assert!(timers.advance(0.3).is_empty());
+        for mut commands in timers.advance(0.3) {
+            commands.run_on(&mut world);
+        }
+        assert_eq!(world.len(), 1);
+
+        advance_cooldowns(&mut world, 0.6);
+        let (_, cooldown) = world
+            .query_mut::<&mut Cooldown>()
+            .into_iter()
+            .next()
+            .unwrap();
+        assert!(!cooldown.is_ready());
+        assert!(!cooldown.trigger(1.0));
+        advance_cooldowns(&mut world, 0.6);
+        let (_, cooldown) = world
+            .query_mut::<&mut Cooldown>()
+            .into_iter()
+            .next()
+            .unwrap();
+        assert!(cooldown.trigger(1.0));
+        assert_eq!(cooldown.remaining, 1.0);
+    }
+}
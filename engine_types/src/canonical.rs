@@ -0,0 +1,104 @@
+//! Canonical JSON for files that live in version control.
+//!
+//! Object keys are always written in sorted order and numbers in their shortest round-trip form,
+//! so saving a scene that hasn't changed produces exactly the same bytes. Values are never
+//! changed, only how they're written.
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+
+/// Serialise `value` as pretty-printed, canonical JSON (with a trailing newline).
+pub fn to_string<T: Serialize>(value: &T) -> serde_json::Result<String> {
+    let value = canonicalise(to_value(value)?);
+    let mut string = serde_json::to_string_pretty(&value)?;
+    string.push('\n');
+    Ok(string)
+}
+
+/// Like `serde_json::to_value`, except `f32`s come out as the shortest decimal that reads back
+/// as the same `f32` (`0.1` rather than `0.10000000149011612`).
+pub fn to_value<T: Serialize>(value: &T) -> serde_json::Result<Value> {
+    // The serialiser writes f32s with f32 precision, but `Value` widens them to f64 exactly
+    serde_json::from_str(&serde_json::to_string(value)?)
+}
+
+/// Sort every object by key and write `-0.0` as `0.0`.
+pub fn canonicalise(value: Value) -> Value {
+    match value {
+        Value::Number(number) => Value::Number(canonical_number(number)),
+        Value::Array(values) => Value::Array(values.into_iter().map(canonicalise).collect()),
+        Value::Object(map) => {
+            // serde_json only sorts keys when `preserve_order` is off, and any crate in the
+            // graph can turn it on, so don't rely on it.
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, canonicalise(value)))
+                    .collect::<Map<_, _>>(),
+            )
+        }
+        other => other,
+    }
+}
+
+fn canonical_number(number: Number) -> Number {
+    if !number.is_f64() {
+        return number;
+    }
+
+    let Some(float) = number.as_f64() else {
+        return number;
+    };
+
+    // They're equal, but they'd be written differently
+    if float == 0.0 && float.is_sign_negative() {
+        return Number::from_f64(0.0).unwrap_or(number);
+    }
+
+    number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_string() {
+        let value = serde_json::json!({
+            "scale": [1.0, 1.0, 1.0],
+            "position": [0.1, -0.0, 3],
+        });
+
+        let expected = r#"{
+  "position": [
+    0.1,
+    0.0,
+    3
+  ],
+  "scale": [
+    1.0,
+    1.0,
+    1.0
+  ]
+}
+"#;
+        assert_eq!(to_string(&value).unwrap(), expected);
+
+        // Writing canonical JSON again must not change it
+        let reparsed: Value = serde_json::from_str(expected).unwrap();
+        assert_eq!(to_string(&reparsed).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_precision() {
+        // Nothing is rounded, whatever its precision
+        let value = (2.000000000000001_f64, 6.399999999999993_f64, 0.1_f32);
+        let written = to_string(&value).unwrap();
+        let read: (f64, f64, f32) = serde_json::from_str(&written).unwrap();
+        assert_eq!(read, value);
+
+        // f32s aren't written as their f64 widening
+        assert_eq!(to_value(&0.1_f32).unwrap(), serde_json::json!(0.1));
+    }
+}
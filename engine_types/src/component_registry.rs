@@ -1,21 +1,33 @@
-use std::{any::TypeId, collections::HashMap, path::Component};
+use std::{
+    any::TypeId,
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Component,
+};
 
 use crate::{
     CanYak, PaintFn,
-    components::{GLTFAsset, Transform},
+    components::{GLTFAsset, Name, Parent, Transform},
+    entity_ref::{EntityRef, MapEntities},
 };
-use hecs::EntityBuilderClone;
+use hecs::{BuiltEntityClone, EntityBuilderClone};
 use serde_json::Value;
 
 type DeserialiseFn = Box<dyn Fn(&mut EntityBuilderClone, serde_json::Value) + Send + Sync>;
 type SerialiseFn = Box<dyn Fn(&hecs::World, hecs::Entity) -> serde_json::Value + Send + Sync>;
+type MapEntitiesFn =
+    Box<dyn Fn(&hecs::World, hecs::Entity, &mut dyn FnMut(&mut EntityRef)) + Send + Sync>;
 
 pub struct ComponentRegistry {
     deserialisers: HashMap<String, DeserialiseFn>,
     serialisers: HashMap<String, SerialiseFn>,
     gui: HashMap<TypeId, PaintFn>,
+    entity_mappers: HashMap<TypeId, MapEntitiesFn>,
     type_id_to_name: HashMap<TypeId, String>,
     name_to_type_id: HashMap<String, TypeId>,
+    /// Components that are left out of save games
+    non_persistent: HashSet<TypeId>,
+    /// Components that are left out of scenes
+    runtime_only: HashSet<TypeId>,
 }
 
 impl Default for ComponentRegistry {
@@ -24,12 +36,19 @@ impl Default for ComponentRegistry {
             deserialisers: Default::default(),
             serialisers: Default::default(),
             gui: Default::default(),
+            entity_mappers: Default::default(),
             type_id_to_name: Default::default(),
             name_to_type_id: Default::default(),
+            non_persistent: Default::default(),
+            runtime_only: Default::default(),
         };
 
         registry.register_component::<GLTFAsset>();
         registry.register_component::<Transform>();
+        registry.register_component::<Name>();
+        registry.register_component::<Parent>();
+        // Rebuilt from the prefab every time it's spawned
+        registry.set_runtime_only::<Parent>(true);
 
         registry
     }
@@ -64,7 +83,7 @@ impl ComponentRegistry {
             name.clone(),
             Box::new(move |world, entity| {
                 let component = Component::clone(&world.get::<&Component>(entity).unwrap());
-                serde_json::to_value(component).unwrap()
+                crate::canonical::to_value(&component).unwrap()
             }),
         );
 
@@ -74,6 +93,75 @@ impl ComponentRegistry {
         self.name_to_type_id.insert(name.clone(), type_id);
     }
 
+    /// Let the registry resolve the [`EntityRef`]s inside `Component` when scenes are spawned.
+    pub fn register_entity_mapper<Component>(&mut self)
+    where
+        Component: MapEntities + Send + Sync + 'static,
+    {
+        self.entity_mappers.insert(
+            TypeId::of::<Component>(),
+            Box::new(move |world, entity, map| {
+                world
+                    .get::<&mut Component>(entity)
+                    .unwrap()
+                    .map_entities(map);
+            }),
+        );
+    }
+
+    /// Call `map` with every [`EntityRef`] on `entity`, along with the name of the component
+    /// it lives in.
+    pub fn map_entity_refs(
+        &self,
+        world: &hecs::World,
+        entity: hecs::Entity,
+        map: &mut dyn FnMut(&str, &mut EntityRef),
+    ) {
+        let Ok(entity_ref) = world.entity(entity) else {
+            return;
+        };
+
+        for type_id in entity_ref.component_types() {
+            let (Some(mapper), Some(name)) =
+                (self.entity_mappers.get(&type_id), self.get_name(type_id))
+            else {
+                continue;
+            };
+
+            mapper(world, entity, &mut |entity_ref| map(name, entity_ref));
+        }
+    }
+
+    /// Components are saved in save games unless they're marked otherwise here. Anything that's
+    /// rebuilt at runtime, or points at things that won't exist after loading, should opt out.
+    pub fn set_persistent<Component: 'static>(&mut self, persistent: bool) {
+        let type_id = TypeId::of::<Component>();
+        if persistent {
+            self.non_persistent.remove(&type_id);
+        } else {
+            self.non_persistent.insert(type_id);
+        }
+    }
+
+    pub fn is_persistent(&self, component_type_id: TypeId) -> bool {
+        !self.non_persistent.contains(&component_type_id)
+    }
+
+    /// Runtime-only components are never written to scenes, eg. because they're rebuilt when
+    /// the scene is loaded or point at live entities.
+    pub fn set_runtime_only<Component: 'static>(&mut self, runtime_only: bool) {
+        let type_id = TypeId::of::<Component>();
+        if runtime_only {
+            self.runtime_only.insert(type_id);
+        } else {
+            self.runtime_only.remove(&type_id);
+        }
+    }
+
+    pub fn is_runtime_only(&self, component_type_id: TypeId) -> bool {
+        self.runtime_only.contains(&component_type_id)
+    }
+
     pub fn add_component_to_builder(
         &self,
         component_name: impl AsRef<str>,
@@ -98,6 +186,90 @@ impl ComponentRegistry {
         serialiser(world, entity)
     }
 
+    /// Serialise every registered component on `entity`, keyed by component name.
+    pub fn serialise_entity(
+        &self,
+        world: &hecs::World,
+        entity: hecs::Entity,
+    ) -> BTreeMap<String, Value> {
+        let Ok(entity_ref) = world.entity(entity) else {
+            return Default::default();
+        };
+
+        entity_ref
+            .component_types()
+            .filter_map(|type_id| self.get_name(type_id))
+            .map(|name| {
+                let value = self.get_component_as_value(name, world, entity);
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    /// Like [`ComponentRegistry::serialise_entity`], leaving out runtime-only components.
+    pub fn serialise_for_scene(
+        &self,
+        world: &hecs::World,
+        entity: hecs::Entity,
+    ) -> BTreeMap<String, Value> {
+        let mut components = self.serialise_entity(world, entity);
+        components.retain(|name, _| {
+            self.get_type_id(name)
+                .is_some_and(|type_id| !self.is_runtime_only(type_id))
+        });
+        components
+    }
+
+    /// Like [`ComponentRegistry::serialise_entity`], leaving out components that opted out of
+    /// persistence.
+    pub fn serialise_persistent(
+        &self,
+        world: &hecs::World,
+        entity: hecs::Entity,
+    ) -> BTreeMap<String, Value> {
+        let mut components = self.serialise_entity(world, entity);
+        components.retain(|name, _| {
+            self.get_type_id(name)
+                .is_some_and(|type_id| self.is_persistent(type_id))
+        });
+        components
+    }
+
+    /// Build an entity from components serialised by this registry. Components it doesn't know
+    /// about are skipped.
+    pub fn build_entity(
+        &self,
+        components: impl IntoIterator<Item = (String, Value)>,
+    ) -> BuiltEntityClone {
+        let mut builder = EntityBuilderClone::new();
+        for (name, value) in components {
+            if let Some(deserialise) = self.deserialisers.get(&name) {
+                deserialise(&mut builder, value);
+            }
+        }
+        builder.build()
+    }
+
+    /// A hash of every registered component in the world. It's the same between runs of the same
+    /// build, so a recording and its replay can be compared.
+    pub fn hash_world(&self, world: &hecs::World) -> u64 {
+        let mut entities: Vec<hecs::Entity> = world.iter().map(|e| e.entity()).collect();
+        entities.sort_by_key(|entity| entity.to_bits());
+
+        // FNV-1a, since std's hasher is allowed to change
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for entity in entities {
+            let components = self.serialise_entity(world, entity);
+            let serialised = crate::canonical::to_string(&(entity.to_bits(), components)).unwrap();
+            for byte in serialised.bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+
+        hash
+    }
+
     pub fn get_name(&self, component_type_id: TypeId) -> Option<&String> {
         self.type_id_to_name.get(&component_type_id)
     }
@@ -111,7 +283,10 @@ impl ComponentRegistry {
 mod tests {
     use crate::CanYak;
 
-    use crate::ComponentRegistry;
+    use crate::{
+        ComponentRegistry, EntityRef, MapEntities, NodeID,
+        components::{GLTFAsset, Transform},
+    };
 
     #[test]
     fn test_register() {
@@ -144,4 +319,75 @@ mod tests {
         let spawned_component = world.get::<&MyComponent>(entity).unwrap();
         assert_eq!(*spawned_component, component);
     }
+
+    #[test]
+    fn test_entity_refs() {
+        #[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+        struct Door {
+            switch: EntityRef,
+        }
+
+        impl CanYak for Door {
+            fn get_paint_fn() -> crate::PaintFn {
+                Box::new(|_, _| {})
+            }
+        }
+
+        impl MapEntities for Door {
+            fn map_entities(&mut self, map: &mut dyn FnMut(&mut EntityRef)) {
+                self.switch.map_entities(map);
+            }
+        }
+
+        let mut registry = ComponentRegistry::default();
+        registry.register_component::<Door>();
+        registry.register_entity_mapper::<Door>();
+
+        let mut world = hecs::World::new();
+        let switch = world.spawn(());
+
+        let mut entity_builder = hecs::EntityBuilderClone::new();
+        registry.add_component_to_builder(
+            "Door",
+            serde_json::json!({ "switch": 7 }),
+            &mut entity_builder,
+        );
+        let door = world.spawn(&entity_builder.build());
+        assert_eq!(world.get::<&Door>(door).unwrap().switch.entity(), None);
+
+        registry.map_entity_refs(&world, door, &mut |component_name, entity_ref| {
+            assert_eq!(component_name, "Door");
+            assert_eq!(entity_ref.node_id(), NodeID::new(7));
+            entity_ref.resolve(NodeID::new(3), Some(switch));
+        });
+
+        assert_eq!(
+            world.get::<&Door>(door).unwrap().switch.entity(),
+            Some(switch)
+        );
+        assert_eq!(
+            registry.get_component_as_value("Door", &world, door),
+            serde_json::json!({ "switch": 3 })
+        );
+    }
+
+    #[test]
+    fn test_persistence() {
+        let mut registry = ComponentRegistry::default();
+        registry.set_persistent::<GLTFAsset>(false);
+
+        let mut world = hecs::World::new();
+        let asset = GLTFAsset {
+            path: "cube.glb".into(),
+        };
+        let entity = world.spawn((Transform::default(), asset));
+
+        let components = registry.serialise_persistent(&world, entity);
+        assert_eq!(components.keys().collect::<Vec<_>>(), ["Transform"]);
+
+        let built = registry.build_entity(components);
+        let entity = world.spawn(&built);
+        assert!(world.get::<&Transform>(entity).is_ok());
+        assert!(world.get::<&GLTFAsset>(entity).is_err());
+    }
 }
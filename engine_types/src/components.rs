@@ -6,7 +6,7 @@ use yakui::label;
 
 use crate::CanYak;
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub struct GLTFAsset {
     pub path: String,
 }
@@ -20,7 +20,7 @@ impl CanYak for GLTFAsset {
     }
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub struct Transform {
     #[serde(default)]
     pub position: glam::Vec3,
@@ -50,6 +50,52 @@ impl CanYak for Transform {
     }
 }
 
+/// What a node was called in its prefab, eg. "Muzzle". Added to every entity spawned from one.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct Name(pub String);
+
+impl CanYak for Name {
+    fn get_paint_fn() -> crate::PaintFn {
+        Box::new(|world, entity| {
+            let name = world.get::<&Name>(entity).unwrap();
+            label(format!("Name: {}", name.0));
+        })
+    }
+}
+
+/// The entity this one sits under in its prefab instance. Set when prefabs are spawned; it
+/// points at a live entity, so it's never written to scenes. Save games do keep it, and point it
+/// at wherever the parent ends up when they're loaded.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(into = "u64", try_from = "u64")]
+pub struct Parent(pub hecs::Entity);
+
+impl From<Parent> for u64 {
+    fn from(parent: Parent) -> Self {
+        parent.0.to_bits().get()
+    }
+}
+
+impl TryFrom<u64> for Parent {
+    type Error = String;
+
+    fn try_from(bits: u64) -> Result<Self, Self::Error> {
+        hecs::Entity::from_bits(bits)
+            .map(Parent)
+            .ok_or_else(|| format!("{bits} isn't an entity"))
+    }
+}
+
+impl CanYak for Parent {
+    fn get_paint_fn() -> crate::PaintFn {
+        Box::new(|world, entity| {
+            let parent = world.get::<&Parent>(entity).unwrap();
+            label(format!("Parent: #{}", parent.0.id()));
+        })
+    }
+}
+
 fn pretty_rotation(rotation: glam::Quat) -> String {
     let (y, x, z) = rotation.to_euler(EulerRot::YXZ);
     format!(
@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+use crate::NodeID;
+
+/// A reference from one entity to another that survives a save and load.
+///
+/// In files it's written as a [`NodeID`]. Inside a prefab definition that's the index of the
+/// node being referred to; inside a scene it's the node's ID. When the scene is spawned every
+/// reference is resolved to the live [`hecs::Entity`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(from = "NodeID", into = "NodeID")]
+pub struct EntityRef {
+    node_id: NodeID,
+    entity: Option<hecs::Entity>,
+}
+
+impl EntityRef {
+    pub fn new(node_id: NodeID, entity: hecs::Entity) -> Self {
+        Self {
+            node_id,
+            entity: Some(entity),
+        }
+    }
+
+    pub fn node_id(&self) -> NodeID {
+        self.node_id
+    }
+
+    /// `None` until the reference has been resolved, or if what it refers to doesn't exist.
+    pub fn entity(&self) -> Option<hecs::Entity> {
+        self.entity
+    }
+
+    pub fn resolve(&mut self, node_id: NodeID, entity: Option<hecs::Entity>) {
+        self.node_id = node_id;
+        self.entity = entity;
+    }
+}
+
+impl From<NodeID> for EntityRef {
+    fn from(node_id: NodeID) -> Self {
+        Self {
+            node_id,
+            entity: None,
+        }
+    }
+}
+
+impl From<EntityRef> for NodeID {
+    fn from(entity_ref: EntityRef) -> Self {
+        entity_ref.node_id
+    }
+}
+
+/// Implemented by components that hold [`EntityRef`]s, so they can be resolved on spawn.
+/// Register with [`crate::ComponentRegistry::register_entity_mapper`].
+pub trait MapEntities {
+    fn map_entities(&mut self, map: &mut dyn FnMut(&mut EntityRef));
+}
+
+impl MapEntities for EntityRef {
+    fn map_entities(&mut self, map: &mut dyn FnMut(&mut EntityRef)) {
+        map(self)
+    }
+}
+
+impl<T: MapEntities> MapEntities for Option<T> {
+    fn map_entities(&mut self, map: &mut dyn FnMut(&mut EntityRef)) {
+        if let Some(inner) = self {
+            inner.map_entities(map);
+        }
+    }
+}
+
+impl<T: MapEntities> MapEntities for Vec<T> {
+    fn map_entities(&mut self, map: &mut dyn FnMut(&mut EntityRef)) {
+        for inner in self {
+            inner.map_entities(map);
+        }
+    }
+}
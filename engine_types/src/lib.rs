@@ -1,30 +1,43 @@
+pub mod canonical;
 mod component_registry;
 pub mod components;
+mod entity_ref;
+mod scene_settings;
+mod system_error;
 pub use component_registry::ComponentRegistry;
-use std::collections::HashMap;
+pub use entity_ref::{EntityRef, MapEntities};
+pub use scene_settings::SceneSettings;
+use std::collections::{BTreeMap, HashMap, HashSet};
+pub use system_error::SystemError;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Clone, Default)]
+#[derive(Deserialize, Serialize, Clone, Default, PartialEq, Debug)]
 pub struct Scene {
+    #[serde(default)]
+    pub settings: SceneSettings,
     pub instances: Vec<PrefabInstance>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Default)]
+#[derive(Deserialize, Serialize, Clone, Default, PartialEq, Debug)]
 pub struct PrefabInstance {
     pub instance_id: InstanceID,
-    pub prefab: String,
-    pub nodes: HashMap<usize, InstanceNode>,
+    /// `None` for entities that were baked from the world rather than spawned from a prefab.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefab: Option<String>,
+    pub nodes: BTreeMap<usize, InstanceNode>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+#[derive(Deserialize, Serialize, Clone, Default, Debug, PartialEq)]
 pub struct InstanceNode {
     pub node_index: usize,
     pub node_id: NodeID,
-    pub overrides: HashMap<String, serde_json::Value>,
+    pub overrides: BTreeMap<String, serde_json::Value>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Default, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Deserialize, Serialize, Clone, Default, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug,
+)]
 pub struct InstanceID(usize);
 
 impl InstanceID {
@@ -83,8 +96,8 @@ pub struct PrefabNode {
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct PrefabDefinition {
     pub name: String,
-    pub components: HashMap<String, serde_json::Value>,
-    #[serde(default)]
+    pub components: BTreeMap<String, serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub children: Vec<PrefabDefinition>,
 }
 
@@ -96,12 +109,18 @@ pub type PaintFn = Box<dyn Fn(&hecs::World, hecs::Entity) + Send + Sync>;
 
 pub struct EditorState<'a> {
     pub play_mode: &'a mut EditorPlayMode,
+    /// Nodes whose changes during play should survive stopping, rather than being reset
+    pub keep_changes: &'a mut HashSet<NodeID>,
     pub world: &'a hecs::World,
     pub scene: &'a mut Scene,
+    /// Set this to write the live world back into the scene and save it.
+    pub save_world: &'a mut bool,
     pub node_entity_map: &'a HashMap<NodeID, hecs::Entity>,
     pub loaded_prefabs: &'a HashMap<String, Prefab>,
     pub prefab_definitions: &'a HashMap<String, PrefabDefinition>,
     pub component_registry: &'a ComponentRegistry,
+    /// The most recent errors from gameplay systems, oldest first
+    pub system_errors: &'a [SystemError],
     pub engine_texture: yakui::TextureId,
     pub screen_size: yakui::Vec2,
     pub scale: f32,
@@ -0,0 +1,33 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::EntityRef;
+
+/// Per-scene environment configuration, loaded into the engine alongside the scene's entities.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct SceneSettings {
+    /// The node the scene is viewed from, using its `Transform`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_camera: Option<EntityRef>,
+    /// What's behind everything, as RGBA
+    pub clear_colour: glam::Vec4,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background_music: Option<String>,
+    /// Anything else, keyed by the name it was registered with (see
+    /// `Engine::register_scene_resource`)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub resources: BTreeMap<String, serde_json::Value>,
+}
+
+impl Default for SceneSettings {
+    fn default() -> Self {
+        Self {
+            active_camera: None,
+            clear_colour: glam::Vec4::new(0., 0., 0., 1.),
+            background_music: None,
+            resources: Default::default(),
+        }
+    }
+}
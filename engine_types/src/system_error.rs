@@ -0,0 +1,12 @@
+/// A system returned an error. The engine keeps the most recent ones around for the editor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemError {
+    pub system: String,
+    pub message: String,
+    /// The frame it happened on
+    pub frame: u64,
+    /// Whether this error got the system disabled
+    pub disabled: bool,
+    /// The system panicked, rather than returning an error
+    pub panicked: bool,
+}
@@ -1,4 +1,4 @@
-use engine_types::{ComponentRegistry, Prefab, PrefabDefinition, PrefabNode};
+use engine_types::{ComponentRegistry, Prefab, PrefabDefinition, PrefabNode, components::Name};
 use hecs::EntityBuilderClone;
 
 pub fn compile(definition: &PrefabDefinition, component_registry: &ComponentRegistry) -> Prefab {
@@ -21,6 +21,8 @@ fn compile_node(
     let my_index = nodes.len();
 
     let mut entity_builder = EntityBuilderClone::new();
+    // So gameplay code can find it again. A `Name` component in the definition wins
+    entity_builder.add(Name(definition.name.clone()));
     for (component_name, component) in &definition.components {
         component_registry.add_component_to_builder(
             component_name,
@@ -112,6 +114,7 @@ mod tests {
             let entity = world.spawn(&node.builder);
             let first = world.get::<&FirstComponent>(entity).unwrap();
             let next = world.get::<&NextComponent>(entity).unwrap();
+            assert_eq!(world.get::<&Name>(entity).unwrap().0, node.name);
 
             if node.index == 0 {
                 assert_eq!(first.a, 1);
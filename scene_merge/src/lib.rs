@@ -0,0 +1,675 @@
+use engine_types::{InstanceID, InstanceNode, NodeID, PrefabInstance, Scene, SceneSettings};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, btree_map};
+
+pub struct MergeResult {
+    pub scene: Scene,
+    pub conflicts: Vec<Conflict>,
+    /// Instances that both sides added with the same ID; theirs is given a fresh ID (old, new).
+    pub renumbered: Vec<(InstanceID, InstanceID)>,
+    /// Nodes they added with an ID we'd already used; theirs is given a fresh ID (old, new).
+    pub renumbered_nodes: Vec<(NodeID, NodeID)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// `None` for conflicts in the scene's settings
+    pub instance_id: Option<InstanceID>,
+    pub node_id: Option<NodeID>,
+    pub component: Option<String>,
+    pub kind: ConflictKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Both sides changed the same thing to different values. Ours is kept.
+    BothModified,
+    /// One side deleted something the other side changed. The changed version is kept.
+    ModifiedAndDeleted,
+    /// Both sides added a node for the same part of the prefab. Ours is kept, and theirs is moved
+    /// to a copy of the instance so neither is lost.
+    SameSlot { moved_to: InstanceID },
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.instance_id {
+            Some(instance_id) => write!(f, "instance #{instance_id}")?,
+            None => write!(f, "scene settings")?,
+        }
+        if let Some(node_id) = self.node_id {
+            write!(f, ", node #{node_id}")?;
+        }
+        if let Some(component) = &self.component {
+            write!(f, ", {component}")?;
+        }
+
+        match self.kind {
+            ConflictKind::BothModified => write!(f, ": modified on both sides"),
+            ConflictKind::ModifiedAndDeleted => {
+                write!(f, ": modified on one side, deleted on the other")
+            }
+            ConflictKind::SameSlot { moved_to } => write!(
+                f,
+                ": added in the same place on both sides, theirs was moved to instance #{moved_to}"
+            ),
+        }
+    }
+}
+
+/// Merge two edited versions of a scene against their common ancestor.
+///
+/// Instances are matched by [`InstanceID`], nodes by [`NodeID`] and overrides by component name.
+/// Anything only one side changed is taken from that side; true conflicts are reported and
+/// resolved in favour of ours, so the resulting scene is always loadable.
+pub fn merge(base: &Scene, ours: &Scene, theirs: &Scene) -> MergeResult {
+    let our_node_ids: HashSet<NodeID> = ours
+        .instances
+        .iter()
+        .flat_map(|instance| instance.nodes.values())
+        .map(|node| node.node_id)
+        .collect();
+
+    let mut merger = Merger {
+        conflicts: Vec::new(),
+        renumbered_nodes: Vec::new(),
+        moved: Vec::new(),
+        next_instance_id: [base, ours, theirs]
+            .iter()
+            .flat_map(|scene| &scene.instances)
+            .map(|instance| instance.instance_id.as_raw() + 1)
+            .max()
+            .unwrap_or_default(),
+        next_node_id: [base, ours, theirs]
+            .iter()
+            .flat_map(|scene| &scene.instances)
+            .flat_map(|instance| instance.nodes.values())
+            .map(|node| node.node_id.as_raw() + 1)
+            .max()
+            .unwrap_or_default(),
+        our_node_ids,
+    };
+
+    let base_instances = index_instances(base);
+    let our_instances = index_instances(ours);
+    let their_instances = index_instances(theirs);
+
+    // Keep our ordering, then append anything only they have
+    let mut instance_ids: Vec<InstanceID> = ours.instances.iter().map(|i| i.instance_id).collect();
+    instance_ids.extend(
+        theirs
+            .instances
+            .iter()
+            .map(|i| i.instance_id)
+            .filter(|id| !our_instances.contains_key(id)),
+    );
+
+    let mut instances = Vec::new();
+    let mut renumber = Vec::new();
+
+    for instance_id in instance_ids {
+        let base = base_instances.get(&instance_id).copied();
+        let ours = our_instances.get(&instance_id).copied();
+        let theirs = their_instances.get(&instance_id).copied();
+
+        match (base, ours, theirs) {
+            (Some(base), Some(ours), Some(theirs)) => {
+                instances.push(merger.merge_instance(base, ours, theirs));
+            }
+            // Both sides spawned something and picked the same ID
+            (None, Some(ours), Some(theirs)) if ours != theirs => {
+                instances.push(ours.clone());
+                renumber.push(theirs);
+            }
+            // They spawned something whose nodes clash with ours
+            (None, None, Some(theirs))
+                if theirs
+                    .nodes
+                    .values()
+                    .any(|node| merger.our_node_ids.contains(&node.node_id)) =>
+            {
+                renumber.push(theirs);
+            }
+            _ => {
+                let (instance, conflict) = merge_value(base, ours, theirs);
+                if let Some(kind) = conflict {
+                    merger.conflict(Some(instance_id), None, None, kind);
+                }
+                instances.extend(instance);
+            }
+        }
+    }
+
+    let mut renumbered = Vec::new();
+    for theirs in renumber {
+        let instance = merger.renumber(theirs);
+        renumbered.push((theirs.instance_id, instance.instance_id));
+        instances.push(instance);
+    }
+    instances.append(&mut merger.moved);
+
+    let settings = merger.merge_settings(&base.settings, &ours.settings, &theirs.settings);
+
+    MergeResult {
+        scene: Scene {
+            settings,
+            instances,
+        },
+        conflicts: merger.conflicts,
+        renumbered,
+        renumbered_nodes: merger.renumbered_nodes,
+    }
+}
+
+struct Merger {
+    conflicts: Vec<Conflict>,
+    renumbered_nodes: Vec<(NodeID, NodeID)>,
+    /// Copies of instances holding their nodes that clashed with ours
+    moved: Vec<PrefabInstance>,
+    next_instance_id: usize,
+    next_node_id: usize,
+    /// Every node ID in use on our side, in any instance
+    our_node_ids: HashSet<NodeID>,
+}
+
+impl Merger {
+    fn merge_instance(
+        &mut self,
+        base: &PrefabInstance,
+        ours: &PrefabInstance,
+        theirs: &PrefabInstance,
+    ) -> PrefabInstance {
+        let instance_id = ours.instance_id;
+
+        let (prefab, conflict) =
+            merge_value(Some(&base.prefab), Some(&ours.prefab), Some(&theirs.prefab));
+        if let Some(kind) = conflict {
+            self.conflict(Some(instance_id), None, None, kind);
+        }
+
+        let base_nodes = index_nodes(base);
+        let our_nodes = index_nodes(ours);
+        let their_nodes = index_nodes(theirs);
+        let node_ids: BTreeSet<NodeID> = base_nodes
+            .keys()
+            .chain(our_nodes.keys())
+            .chain(their_nodes.keys())
+            .copied()
+            .collect();
+
+        // Nodes from our side are placed first, so they keep their slots
+        let mut merged = Vec::new();
+        let mut added_by_them = Vec::new();
+        for node_id in node_ids {
+            let base = base_nodes.get(&node_id).copied();
+            let ours = our_nodes.get(&node_id).copied();
+            let theirs = their_nodes.get(&node_id).copied();
+
+            match (base, ours, theirs) {
+                (Some(base), Some(ours), Some(theirs)) => {
+                    merged.push(self.merge_node(instance_id, base, ours, theirs));
+                }
+                // Both sides added a node and picked the same ID
+                (None, Some(ours), Some(theirs)) if ours != theirs => {
+                    merged.push(ours.clone());
+                    added_by_them.push(self.renumber_node(theirs));
+                }
+                // They added a node with an ID we've used somewhere else
+                (None, None, Some(theirs)) if self.our_node_ids.contains(&node_id) => {
+                    added_by_them.push(self.renumber_node(theirs));
+                }
+                _ => {
+                    let (node, conflict) = merge_value(base, ours, theirs);
+                    if let Some(kind) = conflict {
+                        self.conflict(Some(instance_id), Some(node_id), None, kind);
+                    }
+                    match (node, ours) {
+                        (Some(node), Some(_)) => merged.push(node),
+                        (Some(node), None) => added_by_them.push(node),
+                        (None, _) => {}
+                    }
+                }
+            }
+        }
+
+        let prefab = prefab.unwrap_or_else(|| ours.prefab.clone());
+        let mut nodes = BTreeMap::new();
+        for node in merged.into_iter().chain(added_by_them) {
+            if let btree_map::Entry::Vacant(slot) = nodes.entry(node.node_index) {
+                slot.insert(node);
+                continue;
+            }
+
+            // Two different nodes claiming the same slot in the prefab, so one of them has to
+            // live somewhere else
+            let node_id = node.node_id;
+            let moved_to = InstanceID::new(self.next_instance_id);
+            self.next_instance_id += 1;
+            self.moved.push(PrefabInstance {
+                instance_id: moved_to,
+                prefab: prefab.clone(),
+                nodes: [(node.node_index, node)].into(),
+            });
+            self.conflict(
+                Some(instance_id),
+                Some(node_id),
+                None,
+                ConflictKind::SameSlot { moved_to },
+            );
+        }
+
+        PrefabInstance {
+            instance_id,
+            prefab,
+            nodes,
+        }
+    }
+
+    fn merge_node(
+        &mut self,
+        instance_id: InstanceID,
+        base: &InstanceNode,
+        ours: &InstanceNode,
+        theirs: &InstanceNode,
+    ) -> InstanceNode {
+        let node_id = ours.node_id;
+
+        let (node_index, conflict) = merge_value(
+            Some(&base.node_index),
+            Some(&ours.node_index),
+            Some(&theirs.node_index),
+        );
+        if let Some(kind) = conflict {
+            self.conflict(Some(instance_id), Some(node_id), None, kind);
+        }
+
+        let components: BTreeSet<&String> = base
+            .overrides
+            .keys()
+            .chain(ours.overrides.keys())
+            .chain(theirs.overrides.keys())
+            .collect();
+
+        let mut overrides = BTreeMap::new();
+        for component in components {
+            let (value, conflict) = merge_value(
+                base.overrides.get(component),
+                ours.overrides.get(component),
+                theirs.overrides.get(component),
+            );
+            if let Some(kind) = conflict {
+                self.conflict(
+                    Some(instance_id),
+                    Some(node_id),
+                    Some(component.clone()),
+                    kind,
+                );
+            }
+            if let Some(value) = value {
+                overrides.insert(component.clone(), value);
+            }
+        }
+
+        InstanceNode {
+            node_index: node_index.unwrap_or(ours.node_index),
+            node_id,
+            overrides,
+        }
+    }
+
+    /// Give an instance (and all its nodes) IDs nobody else is using.
+    fn renumber(&mut self, instance: &PrefabInstance) -> PrefabInstance {
+        let mut instance = instance.clone();
+        instance.instance_id = InstanceID::new(self.next_instance_id);
+        self.next_instance_id += 1;
+
+        for node in instance.nodes.values_mut() {
+            node.node_id = NodeID::new(self.next_node_id);
+            self.next_node_id += 1;
+        }
+
+        instance
+    }
+
+    /// Give one of their nodes an ID nobody else is using.
+    fn renumber_node(&mut self, node: &InstanceNode) -> InstanceNode {
+        let mut node = node.clone();
+        let node_id = NodeID::new(self.next_node_id);
+        self.next_node_id += 1;
+        self.renumbered_nodes.push((node.node_id, node_id));
+        node.node_id = node_id;
+        node
+    }
+
+    /// Settings are merged field by field, and each scene resource on its own.
+    fn merge_settings(
+        &mut self,
+        base: &SceneSettings,
+        ours: &SceneSettings,
+        theirs: &SceneSettings,
+    ) -> SceneSettings {
+        let [base_fields, our_fields, their_fields] = [base, ours, theirs].map(settings_fields);
+
+        let keys: BTreeSet<&String> = base_fields
+            .keys()
+            .chain(our_fields.keys())
+            .chain(their_fields.keys())
+            .collect();
+
+        let mut merged = Map::new();
+        for key in keys {
+            let (value, conflict) = merge_value(
+                base_fields.get(key),
+                our_fields.get(key),
+                their_fields.get(key),
+            );
+            if let Some(kind) = conflict {
+                self.conflict(None, None, Some(key.clone()), kind);
+            }
+            if let Some(value) = value {
+                insert_settings_field(&mut merged, key, value);
+            }
+        }
+
+        serde_json::from_value(Value::Object(merged)).unwrap_or_else(|_| ours.clone())
+    }
+
+    fn conflict(
+        &mut self,
+        instance_id: Option<InstanceID>,
+        node_id: Option<NodeID>,
+        component: Option<String>,
+        kind: ConflictKind,
+    ) {
+        self.conflicts.push(Conflict {
+            instance_id,
+            node_id,
+            component,
+            kind,
+        });
+    }
+}
+
+/// Three-way merge of a single value, where `None` means "doesn't exist on this side".
+///
+/// On conflict the returned value is the one we keep: ours, or whichever side still has it if
+/// the other deleted it.
+fn merge_value<T: PartialEq + Clone>(
+    base: Option<&T>,
+    ours: Option<&T>,
+    theirs: Option<&T>,
+) -> (Option<T>, Option<ConflictKind>) {
+    if ours == theirs || theirs == base {
+        return (ours.cloned(), None);
+    }
+
+    if ours == base {
+        return (theirs.cloned(), None);
+    }
+
+    match (ours, theirs) {
+        (Some(ours), Some(_)) => (Some(ours.clone()), Some(ConflictKind::BothModified)),
+        (Some(ours), None) => (Some(ours.clone()), Some(ConflictKind::ModifiedAndDeleted)),
+        (None, theirs) => (theirs.cloned(), Some(ConflictKind::ModifiedAndDeleted)),
+    }
+}
+
+/// Flatten settings into their fields, with each resource as `resources.<name>`.
+fn settings_fields(settings: &SceneSettings) -> BTreeMap<String, Value> {
+    let Ok(Value::Object(mut fields)) = serde_json::to_value(settings) else {
+        return Default::default();
+    };
+
+    let resources = match fields.remove("resources") {
+        Some(Value::Object(resources)) => resources,
+        _ => Map::new(),
+    };
+
+    fields
+        .into_iter()
+        .chain(
+            resources
+                .into_iter()
+                .map(|(name, value)| (format!("resources.{name}"), value)),
+        )
+        .collect()
+}
+
+fn insert_settings_field(fields: &mut Map<String, Value>, key: &str, value: Value) {
+    match key.strip_prefix("resources.") {
+        Some(name) => {
+            let resources = fields
+                .entry("resources")
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(resources) = resources {
+                resources.insert(name.to_string(), value);
+            }
+        }
+        None => {
+            fields.insert(key.to_string(), value);
+        }
+    }
+}
+
+fn index_instances(scene: &Scene) -> HashMap<InstanceID, &PrefabInstance> {
+    scene
+        .instances
+        .iter()
+        .map(|instance| (instance.instance_id, instance))
+        .collect()
+}
+
+fn index_nodes(instance: &PrefabInstance) -> HashMap<NodeID, &InstanceNode> {
+    instance
+        .nodes
+        .values()
+        .map(|node| (node.node_id, node))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn instance(
+        instance_id: usize,
+        node_id: usize,
+        overrides: serde_json::Value,
+    ) -> PrefabInstance {
+        let overrides = serde_json::from_value(overrides).unwrap();
+        PrefabInstance {
+            instance_id: InstanceID::new(instance_id),
+            prefab: Some("cube".into()),
+            nodes: [(
+                0,
+                InstanceNode {
+                    node_index: 0,
+                    node_id: NodeID::new(node_id),
+                    overrides,
+                },
+            )]
+            .into(),
+        }
+    }
+
+    fn scene(instances: Vec<PrefabInstance>) -> Scene {
+        Scene {
+            settings: Default::default(),
+            instances,
+        }
+    }
+
+    #[test]
+    fn test_merge_overrides() {
+        let base = scene(vec![instance(
+            0,
+            0,
+            json!({"Transform": {"position": [0, 0, 0]}}),
+        )]);
+        let ours = scene(vec![instance(
+            0,
+            0,
+            json!({"Transform": {"position": [1, 0, 0]}}),
+        )]);
+        let theirs = scene(vec![instance(
+            0,
+            0,
+            json!({
+                "Transform": {"position": [0, 0, 0]},
+                "GLTFAsset": {"path": "sphere.glb"},
+            }),
+        )]);
+
+        let result = merge(&base, &ours, &theirs);
+        assert!(result.conflicts.is_empty());
+        assert_eq!(
+            result.scene,
+            scene(vec![instance(
+                0,
+                0,
+                json!({
+                    "Transform": {"position": [1, 0, 0]},
+                    "GLTFAsset": {"path": "sphere.glb"},
+                }),
+            )])
+        );
+    }
+
+    #[test]
+    fn test_merge_conflict() {
+        let base = scene(vec![instance(
+            0,
+            0,
+            json!({"Transform": {"position": [0, 0, 0]}}),
+        )]);
+        let ours = scene(vec![instance(
+            0,
+            0,
+            json!({"Transform": {"position": [1, 0, 0]}}),
+        )]);
+        let theirs = scene(vec![instance(
+            0,
+            0,
+            json!({"Transform": {"position": [2, 0, 0]}}),
+        )]);
+
+        let result = merge(&base, &ours, &theirs);
+        assert_eq!(result.scene, ours);
+        assert_eq!(
+            result.conflicts,
+            vec![Conflict {
+                instance_id: Some(InstanceID::new(0)),
+                node_id: Some(NodeID::new(0)),
+                component: Some("Transform".into()),
+                kind: ConflictKind::BothModified,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_merge_added_and_deleted() {
+        let base = scene(vec![instance(0, 0, json!({})), instance(1, 1, json!({}))]);
+
+        // We deleted #0 and both added an instance, picking the same IDs
+        let ours = scene(vec![instance(1, 1, json!({})), instance(2, 2, json!({}))]);
+        let theirs = scene(vec![
+            instance(0, 0, json!({})),
+            instance(1, 1, json!({})),
+            instance(2, 2, json!({"Transform": {"position": [1, 0, 0]}})),
+        ]);
+
+        let result = merge(&base, &ours, &theirs);
+        assert!(result.conflicts.is_empty());
+        assert_eq!(
+            result.renumbered,
+            vec![(InstanceID::new(2), InstanceID::new(3))]
+        );
+        assert_eq!(
+            result.scene,
+            scene(vec![
+                instance(1, 1, json!({})),
+                instance(2, 2, json!({})),
+                instance(3, 3, json!({"Transform": {"position": [1, 0, 0]}})),
+            ])
+        );
+    }
+
+    fn node(node_index: usize, node_id: usize) -> InstanceNode {
+        InstanceNode {
+            node_index,
+            node_id: NodeID::new(node_id),
+            overrides: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_merge_added_nodes() {
+        let base = scene(vec![instance(0, 0, json!({}))]);
+
+        // We added an instance, and they added a node to the existing one with the same ID
+        let ours = scene(vec![instance(0, 0, json!({})), instance(1, 1, json!({}))]);
+        let mut theirs = base.clone();
+        theirs.instances[0].nodes.insert(1, node(1, 1));
+
+        let result = merge(&base, &ours, &theirs);
+        assert!(result.conflicts.is_empty());
+        assert_eq!(
+            result.renumbered_nodes,
+            vec![(NodeID::new(1), NodeID::new(2))]
+        );
+        assert_eq!(result.scene.instances[0].nodes[&1], node(1, 2));
+        assert_eq!(result.scene.instances[1], instance(1, 1, json!({})));
+    }
+
+    #[test]
+    fn test_merge_same_slot() {
+        let base = scene(vec![instance(0, 0, json!({}))]);
+
+        // Both sides added a node for the prefab's second node
+        let mut ours = base.clone();
+        ours.instances[0].nodes.insert(1, node(1, 1));
+        let mut theirs = base.clone();
+        theirs.instances[0].nodes.insert(1, node(1, 2));
+
+        let result = merge(&base, &ours, &theirs);
+        assert_eq!(result.scene.instances[0], ours.instances[0]);
+
+        // Theirs isn't lost
+        let moved_to = InstanceID::new(1);
+        let moved = &result.scene.instances[1];
+        assert_eq!(moved.instance_id, moved_to);
+        assert_eq!(moved.nodes[&1], node(1, 2));
+        assert_eq!(
+            result.conflicts,
+            vec![Conflict {
+                instance_id: Some(InstanceID::new(0)),
+                node_id: Some(NodeID::new(2)),
+                component: None,
+                kind: ConflictKind::SameSlot { moved_to },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_merge_settings() {
+        let base = scene(vec![]);
+
+        let mut ours = base.clone();
+        ours.settings.clear_colour = glam::Vec4::ONE;
+
+        let mut theirs = base.clone();
+        theirs
+            .settings
+            .resources
+            .insert("Level".into(), json!({"name": "1-1"}));
+
+        let result = merge(&base, &ours, &theirs);
+        assert!(result.conflicts.is_empty());
+        assert_eq!(result.scene.settings.clear_colour, glam::Vec4::ONE);
+        assert_eq!(
+            result.scene.settings.resources.get("Level"),
+            Some(&json!({"name": "1-1"}))
+        );
+    }
+}
@@ -64,6 +64,9 @@ impl GameplayLib {
         let lib = copy_and_load_lib(&self.lib_name, &self.lib_path, self.version)?;
 
         if let Some(engine) = engine {
+            // Anything that points into the old library has to go before it does
+            engine.prepare_for_reload();
+
             // Get the exported function
             let reload: Symbol<unsafe extern "C" fn(*mut Engine)> =
                 unsafe { lib.get(b"reload\0") }?;